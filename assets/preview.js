@@ -0,0 +1,25 @@
+// Live preview of a Markdown field, rendered by the server so it matches the published page.
+//
+// Usage: <textarea data-preview="preview-id"> with <div id="preview-id">.
+for (const field of document.querySelectorAll("textarea[data-preview]")) {
+    const preview = document.getElementById(field.dataset.preview);
+    let timer;
+    let latest = 0;
+    const update = async () => {
+        const request = ++latest;
+        const res = await fetch("/p/preview", {
+            method: "POST",
+            headers: { "Content-Type": "application/x-www-form-urlencoded" },
+            body: new URLSearchParams({ body: field.value }),
+        });
+        // Skip responses which arrive after a newer one was requested.
+        if (res.ok && request === latest) {
+            preview.innerHTML = await res.text();
+        }
+    };
+    field.addEventListener("input", () => {
+        clearTimeout(timer);
+        timer = setTimeout(update, 300);
+    });
+    update();
+}
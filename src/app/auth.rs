@@ -19,9 +19,11 @@ use axum::{
     routing::get,
     Form,
 };
+use axum_extra::extract::CookieJar;
 use lettre::message::Mailbox;
 
-use crate::utils::types::{AppResult, AppRouter, SharedAppState};
+use crate::utils::db::User;
+use crate::utils::types::{AppResult, AppRouter, AppState, SharedAppState};
 
 /// Add all `auth` routes to the router.
 pub fn register_routes(router: AppRouter) -> AppRouter {
@@ -30,6 +32,19 @@ pub fn register_routes(router: AppRouter) -> AppRouter {
         .route("/register", get(register_page).post(register_form))
 }
 
+/// Look up the logged-in user from their `session` cookie, if any.
+pub async fn current_user(state: &AppState, cookies: &CookieJar) -> anyhow::Result<Option<User>> {
+    match cookies.get("session") {
+        Some(session_token) => state.db.lookup_user_from_session_token(session_token.value()).await,
+        None => Ok(None),
+    }
+}
+
+/// Whether the logged-in user is an organizer.
+pub async fn is_organizer(state: &AppState, cookies: &CookieJar) -> anyhow::Result<bool> {
+    Ok(current_user(state, cookies).await?.is_some_and(|user| user.organizer))
+}
+
 /// Display the login page.
 async fn login_page(
     State(state): State<SharedAppState>,
@@ -100,6 +115,9 @@ async fn register_form(
     };
 
     let user_id = state.db.create_user(&form.first_name, &form.last_name, &email).await?;
+    if form.newsletter {
+        state.db.subscribe_to_newsletter(user_id).await?;
+    }
     let session_token = state.db.create_session_token(user_id).await?;
 
     // TODO: Expiration date on the cookie
@@ -114,4 +132,7 @@ struct RegisterForm {
     token: String,
     first_name: String,
     last_name: String,
+    /// Whether they opted in to the newsletter.
+    #[serde(default)]
+    newsletter: bool,
 }
@@ -0,0 +1,371 @@
+//! Comments on posts and events, with a moderation queue.
+//!
+//! Comments from anyone but organizers wait in the queue until an organizer
+//! approves them. Replies are threaded one level deep, under top-level
+//! comments. Authors can edit a comment for a short while after posting it,
+//! and delete it at any time, which leaves a placeholder if it has replies.
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form,
+};
+use axum_extra::extract::CookieJar;
+use chrono::{TimeDelta, Utc};
+
+use crate::app::auth::current_user;
+use crate::app::events::empty_as_none_parsed;
+use crate::app::jobs;
+use crate::utils::db::{Comment, CommentStatus, CommentTarget, PostStatus, User};
+use crate::utils::markdown;
+use crate::utils::types::{AppResult, AppRouter, AppState, SharedAppState};
+
+/// How long after posting a comment its author can still edit it.
+const EDIT_WINDOW: TimeDelta = TimeDelta::minutes(15);
+/// Longest comment accepted, in characters.
+const MAX_COMMENT_CHARS: usize = 5000;
+/// Number of moderated comments shown below the queue.
+const RECENT_COMMENTS: u32 = 50;
+
+/// Add all `comment` routes to the router.
+pub fn register_routes(router: AppRouter) -> AppRouter {
+    router
+        .route("/p/:post/comments", post(create_post_comment_form))
+        .route("/e/:event_id/comments", post(create_event_comment_form))
+        .route("/comments", get(moderation_page))
+        .route("/comments/:comment_id/edit", post(edit_comment_form))
+        .route("/comments/:comment_id/delete", post(delete_comment_form))
+        .route("/comments/:comment_id/status", post(moderate_comment_form))
+        .route("/comments/:comment_id/ban", post(ban_commenter_form))
+        .route("/comments/bans/:user_id/delete", post(unban_commenter_form))
+}
+
+/// A top-level comment and its replies, as shown under a post or event.
+#[derive(serde::Serialize)]
+pub(crate) struct Thread {
+    comment: CommentView,
+    replies: Vec<CommentView>,
+}
+
+/// A comment, with what the viewer can do with it.
+#[derive(serde::Serialize)]
+struct CommentView {
+    #[serde(flatten)]
+    comment: Comment,
+    /// Body rendered to HTML, or empty for a placeholder.
+    html: String,
+    /// Whether this stands in for a comment the viewer can't see, which has replies they can.
+    placeholder: bool,
+    can_edit: bool,
+    can_delete: bool,
+}
+
+impl CommentView {
+    fn new(comment: Comment, viewer: Option<&User>) -> Self {
+        let author = viewer.is_some_and(|u| u.id == comment.user_id);
+        let organizer = viewer.is_some_and(|u| u.organizer);
+        CommentView {
+            html: markdown::render(&comment.body),
+            placeholder: false,
+            can_edit: author && comment.created_at + EDIT_WINDOW > Utc::now(),
+            can_delete: author || organizer,
+            comment,
+        }
+    }
+
+    /// Stand in for a comment, without giving away who wrote it or what it said.
+    fn placeholder(mut comment: Comment) -> Self {
+        comment.author = String::new();
+        comment.body = String::new();
+        CommentView {
+            comment,
+            html: String::new(),
+            placeholder: true,
+            can_edit: false,
+            can_delete: false,
+        }
+    }
+}
+
+/// Whether `viewer` can see a comment. Until it's approved, only its author and organizers can.
+fn is_visible(comment: &Comment, viewer: Option<&User>) -> bool {
+    let can_see = match viewer {
+        Some(user) => user.organizer || user.id == comment.user_id,
+        None => false,
+    };
+    comment.deleted_at.is_none() && (comment.status == CommentStatus::Approved || can_see)
+}
+
+/// Get the comments on a post or event which `viewer` can see, grouped into threads.
+pub(crate) async fn comment_threads(
+    state: &AppState,
+    target: CommentTarget,
+    target_id: i64,
+    viewer: Option<&User>,
+) -> anyhow::Result<Vec<Thread>> {
+    let mut parents = vec![];
+    let mut replies: HashMap<i64, Vec<Comment>> = HashMap::new();
+    for comment in state.db.get_comments(target, target_id).await? {
+        match comment.parent_id {
+            Some(parent_id) => replies.entry(parent_id).or_default().push(comment),
+            None => parents.push(comment),
+        }
+    }
+
+    let mut threads = vec![];
+    for parent in parents {
+        let thread_replies: Vec<_> = replies
+            .remove(&parent.id)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|r| is_visible(r, viewer))
+            .map(|r| CommentView::new(r, viewer))
+            .collect();
+        let comment = match is_visible(&parent, viewer) {
+            true => CommentView::new(parent, viewer),
+            false if !thread_replies.is_empty() => CommentView::placeholder(parent),
+            false => continue,
+        };
+        threads.push(Thread { comment, replies: thread_replies });
+    }
+    Ok(threads)
+}
+
+/// Process the form to comment on a post, or reply to a comment on it.
+async fn create_post_comment_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(slug): Path<String>,
+    Form(form): Form<CommentForm>,
+) -> AppResult<Response> {
+    let Some(post) = state.db.lookup_post_by_slug(&slug).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    if post.status != PostStatus::Published {
+        return Ok((StatusCode::BAD_REQUEST, "Comments open once the post is published.").into_response());
+    }
+    create_comment(&state, &cookies, CommentTarget::Post, post.id, form).await
+}
+
+/// Process the form to comment on an event, or reply to a comment on it.
+async fn create_event_comment_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+    Form(form): Form<CommentForm>,
+) -> AppResult<Response> {
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    if !event.status.is_public() {
+        return Ok((StatusCode::BAD_REQUEST, "Comments open once the event is published.").into_response());
+    }
+    create_comment(&state, &cookies, CommentTarget::Event, event.id, form).await
+}
+#[derive(serde::Deserialize)]
+struct CommentForm {
+    body: String,
+    /// Comment being replied to.
+    #[serde(default, deserialize_with = "empty_as_none_parsed")]
+    parent_id: Option<i64>,
+}
+
+async fn create_comment(
+    state: &AppState,
+    cookies: &CookieJar,
+    target: CommentTarget,
+    target_id: i64,
+    form: CommentForm,
+) -> AppResult<Response> {
+    let Some(user) = current_user(state, cookies).await? else {
+        return Ok((StatusCode::FORBIDDEN, "Log in to comment.").into_response());
+    };
+    if state.db.is_banned_from_commenting(user.id).await? {
+        return Ok((StatusCode::FORBIDDEN, "You're not allowed to comment.").into_response());
+    }
+    let body = match validate_body(&form.body) {
+        Ok(body) => body,
+        Err(err) => return Ok((StatusCode::BAD_REQUEST, err).into_response()),
+    };
+
+    // Replies to replies go in the same thread, to keep threads one level deep.
+    let parent_id = match form.parent_id {
+        Some(parent_id) => match state.db.lookup_comment_by_id(parent_id).await? {
+            Some(parent)
+                if parent.target == target
+                    && parent.target_id == target_id
+                    && is_visible(&parent, Some(&user)) =>
+            {
+                Some(parent.parent_id.unwrap_or(parent.id))
+            }
+            _ => return Ok((StatusCode::BAD_REQUEST, "That comment can't be replied to.").into_response()),
+        },
+        None => None,
+    };
+
+    let status = match user.organizer {
+        true => CommentStatus::Approved,
+        false => CommentStatus::Pending,
+    };
+    let id = state
+        .db
+        .create_comment(target, target_id, parent_id, user.id, body, status)
+        .await?;
+    jobs::notify_new_comment(state, id).await?;
+    redirect_to_comment(state, id).await
+}
+
+/// Process the form for an author to edit their comment, while they still can.
+async fn edit_comment_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(comment_id): Path<i64>,
+    Form(form): Form<EditComment>,
+) -> AppResult<Response> {
+    let Some(user) = current_user(&state, &cookies).await? else {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+    let Some(comment) = state.db.lookup_comment_by_id(comment_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    if comment.user_id != user.id || comment.deleted_at.is_some() {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    if comment.created_at + EDIT_WINDOW <= Utc::now() {
+        let msg = format!("Comments can only be edited for {} minutes.", EDIT_WINDOW.num_minutes());
+        return Ok((StatusCode::BAD_REQUEST, msg).into_response());
+    }
+    if state.db.is_banned_from_commenting(user.id).await? {
+        return Ok((StatusCode::FORBIDDEN, "You're not allowed to comment.").into_response());
+    }
+    let body = match validate_body(&form.body) {
+        Ok(body) => body,
+        Err(err) => return Ok((StatusCode::BAD_REQUEST, err).into_response()),
+    };
+
+    state.db.update_comment_body(comment.id, body, Utc::now()).await?;
+    redirect_to_comment(&state, comment.id).await
+}
+#[derive(serde::Deserialize)]
+struct EditComment {
+    body: String,
+}
+
+/// Delete a comment, which its author or an organizer can do.
+async fn delete_comment_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(comment_id): Path<i64>,
+) -> AppResult<Response> {
+    let Some(user) = current_user(&state, &cookies).await? else {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+    let Some(comment) = state.db.lookup_comment_by_id(comment_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    if comment.user_id != user.id && !user.organizer {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    state.db.delete_comment(comment.id, Utc::now()).await?;
+    Ok(Redirect::to(&format!("{}#comments", comment.target_url)).into_response())
+}
+
+/// Display the moderation queue, followed by the latest moderated comments and banned users.
+async fn moderation_page(State(state): State<SharedAppState>, cookies: CookieJar) -> AppResult<Response> {
+    if current_user(&state, &cookies).await?.filter(|u| u.organizer).is_none() {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    let render = |comments: Vec<Comment>| -> Vec<_> {
+        comments.into_iter().map(|c| CommentView::new(c, None)).collect()
+    };
+    let mut ctx = tera::Context::new();
+    ctx.insert("pending", &render(state.db.get_pending_comments().await?));
+    ctx.insert("recent", &render(state.db.get_recent_comments(RECENT_COMMENTS).await?));
+    ctx.insert("bans", &state.db.get_comment_bans().await?);
+
+    let html = state.templates.render("comments.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
+}
+
+/// Approve or hide a comment.
+async fn moderate_comment_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(comment_id): Path<i64>,
+    Form(form): Form<ModerateComment>,
+) -> AppResult<Response> {
+    if current_user(&state, &cookies).await?.filter(|u| u.organizer).is_none() {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    if form.status == CommentStatus::Pending {
+        return Ok((StatusCode::BAD_REQUEST, "Comments can only be approved or hidden.").into_response());
+    }
+
+    state.db.set_comment_status(comment_id, form.status).await?;
+    Ok(Redirect::to("/comments").into_response())
+}
+#[derive(serde::Deserialize)]
+struct ModerateComment {
+    status: CommentStatus,
+}
+
+/// Ban whoever wrote a comment from commenting, and hide all of their comments.
+async fn ban_commenter_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(comment_id): Path<i64>,
+) -> AppResult<Response> {
+    let Some(organizer) = current_user(&state, &cookies).await?.filter(|u| u.organizer) else {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+    let Some(comment) = state.db.lookup_comment_by_id(comment_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let author = state.db.lookup_user_by_id(comment.user_id).await?;
+    if author.is_some_and(|u| u.organizer) {
+        return Ok((StatusCode::BAD_REQUEST, "Organizers can't be banned.").into_response());
+    }
+
+    state.db.ban_commenter(comment.user_id, organizer.id, Utc::now()).await?;
+    Ok(Redirect::to("/comments").into_response())
+}
+
+/// Let a banned user comment again.
+async fn unban_commenter_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(user_id): Path<i64>,
+) -> AppResult<Response> {
+    if current_user(&state, &cookies).await?.filter(|u| u.organizer).is_none() {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    state.db.unban_commenter(user_id).await?;
+    Ok(Redirect::to("/comments").into_response())
+}
+
+/// Check a comment isn't empty or too long, returning it trimmed.
+fn validate_body(body: &str) -> Result<&str, String> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err("Comments can't be empty.".into());
+    }
+    if body.chars().count() > MAX_COMMENT_CHARS {
+        return Err(format!("Comments can be at most {MAX_COMMENT_CHARS} characters."));
+    }
+    Ok(body)
+}
+
+/// Redirect to a comment, on the page of whatever it's on.
+async fn redirect_to_comment(state: &AppState, comment_id: i64) -> AppResult<Response> {
+    let Some(comment) = state.db.lookup_comment_by_id(comment_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    Ok(Redirect::to(&format!("{}#comment-{}", comment.target_url, comment.id)).into_response())
+}
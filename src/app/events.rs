@@ -1,116 +1,930 @@
+use std::{str::FromStr, time::Duration};
+
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::{Html, IntoResponse, Response},
-    routing::get,
+    extract::{Path, Query, Request, State},
+    http::{header, Method, StatusCode, Uri},
+    middleware::Next,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
     Form,
 };
-use chrono::Local;
+use axum_extra::extract::CookieJar;
+use chrono::{DateTime, Days, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::{Tz, TZ_VARIANTS};
+use serde::{de::IntoDeserializer, Deserialize, Deserializer};
 
-use crate::utils::types::{AppResult, AppRouter, SharedAppState};
+use crate::app::{
+    auth::{current_user, is_organizer},
+    comments::comment_threads,
+    galleries, jobs,
+};
+use crate::utils::db::{
+    is_unique_violation, CommentTarget, EventCursor, EventFields, EventSearch, EventSort, EventStatus,
+    FieldChange, Revision, RevisionKind,
+};
+use crate::utils::ics::{self, CalendarEvent};
+use crate::utils::slug;
+use crate::utils::types::{AppResult, AppRouter, AppState, SharedAppState};
 
 /// Add all `events` routes to the router.
 pub fn register_routes(router: AppRouter) -> AppRouter {
     router
         .route("/events", get(list_events_page))
+        .route("/events.ics", get(events_calendar))
+        .route("/events/tag/:tag", get(tag_events_page))
+        .route("/events/tags", get(manage_tags_page).post(rename_tag_form))
         .route("/e/new", get(create_event_page).post(create_event_form))
-        .route(
-            "/e/:event_id",
-            // TODO: Move to a separate `/e/:event_id/edit` route, and add a `/e/:event_id` to just view the event.
-            get(update_event_page).post(update_event_form).delete(delete_event),
-        )
+        .route("/e/:event_id", get(event_page))
+        .route("/e/:event_id/edit", get(update_event_page).post(update_event_form))
+        .route("/e/:event_id/delete", post(delete_event))
+        .route("/e/:event_id/status", post(update_event_status_form))
+        .route("/e/:event_id/rsvp", post(rsvp_form))
+        .route("/e/:event_id/duplicate", post(duplicate_event_form))
+        .route("/e/:event_id/history", get(event_history_page))
+        .route("/e/:event_id/history/:revision_id/restore", post(restore_event_revision_form))
+        .route("/e/:event_id/template", post(create_event_template_form))
+        .route("/e/templates/:template_id/delete", post(delete_event_template_form))
+}
+
+/// Path segments under `/e/` used by other routes, which events can't take as their slug.
+pub(crate) const RESERVED_SLUGS: [&str; 2] = ["new", "templates"];
+
+/// Map human-readable event URLs onto the numeric routes.
+///
+/// Runs before routing, so every `/e/:event_id/...` route also works with
+/// the event's slug. Numeric and old-slug URLs permanently redirect to the
+/// current slug, so links shared before a rename keep working.
+pub async fn resolve_event_urls(
+    State(state): State<SharedAppState>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let Some(rest) = req.uri().path().strip_prefix("/e/") else {
+        return next.run(req).await;
+    };
+    let (key, tail) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    if key.is_empty() || RESERVED_SLUGS.contains(&key) {
+        return next.run(req).await;
+    }
+    let (key, tail) = (key.to_string(), tail.to_string());
+
+    // Only redirect page loads; forms keep posting to whatever URL they were rendered with.
+    let redirectable = req.method() == Method::GET || req.method() == Method::HEAD;
+    let cookies = CookieJar::from_headers(req.headers());
+    match resolve_event_url(&state, &cookies, redirectable, &key, &tail).await {
+        Ok(Resolved::Rewrite(path)) => {
+            let uri = match req.uri().query() {
+                Some(query) => format!("{path}?{query}"),
+                None => path,
+            };
+            let Ok(uri) = uri.parse::<Uri>() else {
+                return StatusCode::NOT_FOUND.into_response();
+            };
+            *req.uri_mut() = uri;
+            next.run(req).await
+        }
+        Ok(Resolved::Redirect(path)) => {
+            let location = match req.uri().query() {
+                Some(query) => format!("{path}?{query}"),
+                None => path,
+            };
+            (StatusCode::MOVED_PERMANENTLY, [(header::LOCATION, location)]).into_response()
+        }
+        Ok(Resolved::PassThrough) => next.run(req).await,
+        Ok(Resolved::NotFound) => StatusCode::NOT_FOUND.into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+enum Resolved {
+    /// Serve the request from another path, without the client noticing.
+    Rewrite(String),
+    Redirect(String),
+    PassThrough,
+    NotFound,
 }
 
-/// Display a list of all events.
+async fn resolve_event_url(
+    state: &AppState,
+    cookies: &CookieJar,
+    redirectable: bool,
+    key: &str,
+    tail: &str,
+) -> AppResult<Resolved> {
+    // Don't reveal the slug of an event the visitor isn't allowed to see.
+    let visible = |public: bool| async move { anyhow::Ok(public || is_organizer(state, cookies).await?) };
+
+    if let Ok(event_id) = key.parse::<i64>() {
+        if redirectable {
+            if let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? {
+                if visible(event.status.is_public()).await? {
+                    return Ok(Resolved::Redirect(format!("/e/{}{tail}", event.slug)));
+                }
+            }
+        }
+        return Ok(Resolved::PassThrough);
+    }
+
+    if let Some(event) = state.db.lookup_event_by_slug(key).await? {
+        return Ok(Resolved::Rewrite(format!("/e/{}{tail}", event.id)));
+    }
+    if let Some(event) = state.db.lookup_event_by_old_slug(key).await? {
+        if !redirectable {
+            return Ok(Resolved::Rewrite(format!("/e/{}{tail}", event.id)));
+        }
+        if visible(event.status.is_public()).await? {
+            return Ok(Resolved::Redirect(format!("/e/{}{tail}", event.slug)));
+        }
+    }
+    Ok(Resolved::NotFound)
+}
+
+/// Periodically publish scheduled events once their publish time has passed.
+pub async fn publish_scheduled_events(state: SharedAppState) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        match state.db.publish_scheduled_events(Utc::now()).await {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("published {n} scheduled event(s)"),
+            Err(err) => tracing::error!("publishing scheduled events: {err}"),
+        }
+    }
+}
+
+/// Number of events shown per page of [`list_events_page`].
+const EVENTS_PER_PAGE: u32 = 20;
+
+/// Display a list of events, with search and filters.
 async fn list_events_page(
     State(state): State<SharedAppState>,
+    cookies: CookieJar,
     Query(param): Query<ListEvents>,
 ) -> AppResult<Response> {
-    let events = state.db.get_all_events(Local::now(), param.past.unwrap_or(false)).await?;
+    render_event_list(&state, &cookies, param).await
+}
+
+/// Display the list of events with a tag, e.g. a genre or a series like "Studio Sessions".
+async fn tag_events_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(tag): Path<String>,
+    Query(mut param): Query<ListEvents>,
+) -> AppResult<Response> {
+    param.tag = Some(tag);
+    render_event_list(&state, &cookies, param).await
+}
+
+async fn render_event_list(state: &AppState, cookies: &CookieJar, param: ListEvents) -> AppResult<Response> {
+    let user = current_user(state, cookies).await?;
+    let organizer = user.as_ref().is_some_and(|u| u.organizer);
+
+    let cursor = match param.cursor.as_deref().map(EventCursor::parse) {
+        None => None,
+        Some(Some(cursor)) => Some(cursor),
+        Some(None) => return Ok((StatusCode::BAD_REQUEST, "Invalid cursor.").into_response()),
+    };
+
+    // Date filters are whole days in the site's default timezone.
+    let tz = state.config.app.timezone;
+    let starts_after = param.from.map(|date| start_of_day(date, tz));
+    let starts_before = param.until.map(|date| start_of_day(date + Days::new(1), tz));
+
+    let now = Utc::now();
+    let past = param.past.unwrap_or(false);
+    let search = EventSearch {
+        query: param.q.clone(),
+        ends_after: (!past).then_some(now),
+        ends_before: past.then_some(now),
+        starts_after,
+        starts_before,
+        tag: param.tag.clone(),
+        venue: param.venue.clone(),
+        status: param.status,
+        include_private: organizer,
+        // Show the next upcoming events, or the most recent past events, first.
+        sort: param.sort.unwrap_or(if past { EventSort::Latest } else { EventSort::Soonest }),
+        cursor,
+        limit: EVENTS_PER_PAGE,
+    };
+    let page = state.db.search_events(&search).await?;
+
+    let event_ids: Vec<i64> = page.events.iter().map(|e| e.id).collect();
+    let galleries = state.db.get_event_ids_with_photos(&event_ids).await?;
 
     let mut ctx = tera::Context::new();
-    ctx.insert("events", &events);
+    ctx.insert("events", &page.events);
+    ctx.insert("galleries", &galleries);
+    ctx.insert("next_cursor", &page.next.map(|c| c.to_string()));
+    ctx.insert("params", &param);
+    ctx.insert("organizer", &organizer);
+    ctx.insert("venues", &state.db.get_event_venues().await?);
+    ctx.insert("tags", &state.db.get_event_tags().await?);
 
     let html = state.templates.render("event-list.tera.html", &ctx).unwrap();
     Ok(Html(html).into_response())
 }
-#[derive(serde::Deserialize)]
+/// Query parameters for [`list_events_page`].
+///
+/// These come from a plain HTML form, which submits empty inputs as empty
+/// strings, so those are treated as unset.
+#[derive(serde::Deserialize, serde::Serialize)]
 struct ListEvents {
     past: Option<bool>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    q: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    from: Option<NaiveDate>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    until: Option<NaiveDate>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    tag: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    venue: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    status: Option<EventStatus>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    sort: Option<EventSort>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    cursor: Option<String>,
+}
+
+/// How long past events stay in calendar feeds.
+const CALENDAR_PAST_DAYS: u64 = 30;
+/// Most events included in a calendar feed.
+const CALENDAR_LIMIT: u32 = 500;
+
+/// Serve upcoming events as an iCalendar feed, optionally only those with a `tag`.
+async fn events_calendar(
+    State(state): State<SharedAppState>,
+    Query(param): Query<EventsCalendar>,
+) -> AppResult<Response> {
+    let search = EventSearch {
+        query: None,
+        // Keep recent events around, so they don't vanish from calendars the moment they end.
+        ends_after: Some(Utc::now() - Days::new(CALENDAR_PAST_DAYS)),
+        ends_before: None,
+        starts_after: None,
+        starts_before: None,
+        tag: param.tag.clone(),
+        venue: None,
+        status: None,
+        include_private: false,
+        sort: EventSort::Soonest,
+        cursor: None,
+        limit: CALENDAR_LIMIT,
+    };
+    let events = state.db.search_events(&search).await?.events;
+
+    let host = state.config.app.url.split("://").last().unwrap_or_default();
+    let events: Vec<CalendarEvent> = events
+        .iter()
+        .map(|event| CalendarEvent {
+            uid: format!("event-{}@{host}", event.id),
+            url: format!("{}/e/{}", state.config.app.url, event.slug),
+            summary: &event.title,
+            description: &event.description,
+            location: &event.venue,
+            start: event.start_at,
+            end: event.end_at,
+            updated: event.updated_at.to_utc(),
+            status: match event.status {
+                EventStatus::Cancelled => "CANCELLED",
+                EventStatus::Postponed => "TENTATIVE",
+                _ => "CONFIRMED",
+            },
+        })
+        .collect();
+    let name = match &param.tag {
+        Some(tag) => format!("WLSD: {tag}"),
+        None => "WLSD".to_string(),
+    };
+
+    let headers = [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")];
+    Ok((headers, ics::calendar(&name, &events)).into_response())
+}
+/// Query parameters for [`events_calendar`].
+#[derive(serde::Deserialize)]
+struct EventsCalendar {
+    #[serde(default, deserialize_with = "empty_as_none")]
+    tag: Option<String>,
 }
 
-/// Display the form to create a new event.
-async fn create_event_page(State(state): State<SharedAppState>) -> AppResult<Response> {
-    let ctx = tera::Context::new();
+/// Display all tags, with forms to rename and merge them.
+async fn manage_tags_page(State(state): State<SharedAppState>, cookies: CookieJar) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("tags", &state.db.get_event_tag_counts().await?);
+
+    let html = state.templates.render("tags.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
+}
+
+/// Process the form to rename a tag on every event.
+///
+/// Renaming a tag to one which already exists merges the two.
+async fn rename_tag_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Form(form): Form<RenameTag>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let to = form.to.trim();
+    if to.is_empty() {
+        return Ok((StatusCode::BAD_REQUEST, "New tag name is required.").into_response());
+    }
+    // Tags are entered as a comma-separated list, so a comma would split this one up on the next edit.
+    if to.contains(',') {
+        return Ok((StatusCode::BAD_REQUEST, "Tags can't contain commas.").into_response());
+    }
+
+    state.db.rename_event_tag(&form.from, to).await?;
+    Ok(Redirect::to("/events/tags").into_response())
+}
+#[derive(serde::Deserialize)]
+struct RenameTag {
+    from: String,
+    to: String,
+}
+
+/// Display the form to create a new event, optionally pre-filled from a saved template.
+async fn create_event_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Query(param): Query<CreateEventPage>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let template = match param.template.map(|id| id.parse::<i64>()) {
+        Some(Err(_)) => return Ok(StatusCode::NOT_FOUND.into_response()),
+        Some(Ok(id)) => match state.db.lookup_event_template_by_id(id).await? {
+            Some(template) => Some(template),
+            None => return Ok(StatusCode::NOT_FOUND.into_response()),
+        },
+        None => None,
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("timezones", &timezone_names());
+    ctx.insert(
+        "default_timezone",
+        template.as_ref().map_or(state.config.app.timezone.name(), |t| &t.timezone),
+    );
+    ctx.insert("venues", &state.db.get_event_venues().await?);
+    ctx.insert("template", &template);
+    ctx.insert("templates", &state.db.get_event_templates().await?);
+
     let html = state.templates.render("event-create.tera.html", &ctx).unwrap();
     Ok(Html(html).into_response())
 }
 
+/// Query parameters for [`create_event_page`].
+#[derive(serde::Deserialize)]
+struct CreateEventPage {
+    #[serde(default, deserialize_with = "empty_as_none")]
+    template: Option<String>,
+}
+
 /// Process the form and create a new event.
 async fn create_event_form(
     State(state): State<SharedAppState>,
-    Form(form): Form<CreateEvent>,
-) -> AppResult<impl IntoResponse> {
-    let _event_id = state
-        .db
-        .create_event(&form.title, &form.artist, &form.description, &form.start_date)
-        .await?;
-    Ok("Event created.")
+    cookies: CookieJar,
+    Form(form): Form<EventForm>,
+) -> AppResult<Response> {
+    let Some(editor) = current_user(&state, &cookies).await?.filter(|u| u.organizer) else {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+    let event = match form.parse() {
+        Ok(event) => event,
+        Err(err) => return Ok((StatusCode::BAD_REQUEST, err).into_response()),
+    };
+    if state.db.is_event_slug_taken(&event.slug, None).await? {
+        return Ok((StatusCode::BAD_REQUEST, SLUG_TAKEN).into_response());
+    }
+
+    let event_id = match state.db.create_event(&event, Some(editor.id)).await {
+        // Someone else took the URL since it was checked.
+        Err(err) if is_unique_violation(&err) => {
+            return Ok((StatusCode::BAD_REQUEST, SLUG_TAKEN).into_response())
+        }
+        result => result?,
+    };
+    jobs::schedule_event_emails(&state, event_id).await?;
+    Ok("Event created.".into_response())
 }
-#[derive(serde::Deserialize)]
-struct CreateEvent {
-    title: String,
-    artist: String,
-    description: String,
-    start_date: String,
+
+const SLUG_TAKEN: &str = "That URL is already taken by another event.";
+
+/// Display an event to the public.
+async fn event_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+) -> AppResult<Response> {
+    let user = current_user(&state, &cookies).await?;
+    let organizer = user.as_ref().is_some_and(|u| u.organizer);
+
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    // Unpublished events don't exist as far as the public is concerned.
+    if !event.status.is_public() && !organizer {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    // A hidden photo can't be loaded by the public, so don't show it as the cover either.
+    let cover = match event.cover_photo_id {
+        Some(id) => state.db.lookup_photo_by_id(id).await?.filter(|p| !p.hidden || organizer),
+        None => None,
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("event", &event);
+    ctx.insert("cover", &cover);
+    ctx.insert("tags", &state.db.lookup_tags_by_event_id(event.id).await?);
+    ctx.insert("stages", &state.db.get_stages(event.id).await?);
+    ctx.insert("slots", &state.db.get_slots(event.id).await?);
+    ctx.insert("url", &state.config.app.url);
+    ctx.insert("organizer", &organizer);
+    let comments = comment_threads(&state, CommentTarget::Event, event.id, user.as_ref()).await?;
+    ctx.insert("comments", &comments);
+    ctx.insert("comments_url", &format!("/e/{}/comments", event.id));
+    ctx.insert("comments_open", &event.status.is_public());
+    if let Some(user) = &user {
+        ctx.insert("user", user);
+        ctx.insert("going", &state.db.has_rsvp(event.id, user.id).await?);
+    }
+
+    let html = state.templates.render("event.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
 }
 
 /// Display the form to update an event.
 async fn update_event_page(
     State(state): State<SharedAppState>,
-    Path(event_id): Path<String>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
 ) -> AppResult<Response> {
-    let mut ctx = tera::Context::new();
-    let Some(event) = state.db.lookup_event_by_event_id(&event_id.parse().unwrap()).await? else {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
         return Ok(StatusCode::NOT_FOUND.into_response());
     };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("tags", &state.db.lookup_tags_by_event_id(event.id).await?.join(", "));
     ctx.insert("event", &event);
+    ctx.insert("timezones", &timezone_names());
+    ctx.insert("venues", &state.db.get_event_venues().await?);
+    ctx.insert("guest_allocations", &state.db.get_guest_allocations(event.id).await?);
+    ctx.insert("url", &state.config.app.url);
 
-    let html = state.templates.render("event.tera.html", &ctx).unwrap();
+    let html = state.templates.render("event-edit.tera.html", &ctx).unwrap();
     Ok(Html(html).into_response())
 }
 
 /// Process the form and update an event.
 async fn update_event_form(
     State(state): State<SharedAppState>,
-    Path(event_id): Path<String>,
-    Form(form): Form<UpdateEvent>,
-) -> AppResult<impl IntoResponse> {
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+    Form(form): Form<EventForm>,
+) -> AppResult<Response> {
+    let Some(editor) = current_user(&state, &cookies).await?.filter(|u| u.organizer) else {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+    let event = match form.parse() {
+        Ok(event) => event,
+        Err(err) => return Ok((StatusCode::BAD_REQUEST, err).into_response()),
+    };
+
+    let Some(before) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    if state.db.is_event_slug_taken(&event.slug, Some(before.id)).await? {
+        return Ok((StatusCode::BAD_REQUEST, SLUG_TAKEN).into_response());
+    }
+    match state.db.update_event(before.id, &event, Some(editor.id)).await {
+        Err(err) if is_unique_violation(&err) => {
+            return Ok((StatusCode::BAD_REQUEST, SLUG_TAKEN).into_response())
+        }
+        result => result?,
+    }
+    jobs::schedule_event_emails(&state, before.id).await?;
+    jobs::notify_event_changed(&state, &before).await?;
+    Ok("Event updated.".into_response())
+}
+
+/// Process the form and move an event to a new lifecycle status.
+async fn update_event_status_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+    Form(form): Form<UpdateEventStatus>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    if !event.status.can_transition_to(form.status) {
+        let msg = format!("Can't change an event from {:?} to {:?}.", event.status, form.status);
+        return Ok((StatusCode::BAD_REQUEST, msg).into_response());
+    }
+
+    let timezone: Tz = event.timezone.parse().unwrap_or(state.config.app.timezone);
+    let times = form.publish_at(timezone).and_then(|p| Ok((p, form.rescheduled_at(timezone)?)));
+    let (publish_at, rescheduled_at) = match times {
+        Ok(times) => times,
+        Err(err) => return Ok((StatusCode::BAD_REQUEST, err).into_response()),
+    };
+
     state
         .db
-        .update_event(
-            event_id.parse().unwrap(),
-            &form.title,
-            &form.artist,
-            &form.description,
-            &form.start_date,
-        )
+        .update_event_status(event.id, form.status, publish_at, rescheduled_at)
         .await?;
-    Ok("Event updated.")
+    jobs::notify_event_changed(&state, &event).await?;
+    Ok("Event status updated.".into_response())
 }
 #[derive(serde::Deserialize)]
-struct UpdateEvent {
-    title: String,
-    artist: String,
-    description: String,
-    start_date: String,
+struct UpdateEventStatus {
+    status: EventStatus,
+    #[serde(default)]
+    publish_at: String,
+    #[serde(default)]
+    rescheduled_at: String,
+}
+
+impl UpdateEventStatus {
+    /// When to publish the event, required when scheduling it.
+    fn publish_at(&self, tz: Tz) -> Result<Option<DateTime<Utc>>, String> {
+        if self.status != EventStatus::Scheduled {
+            return Ok(None);
+        }
+        let publish_at = parse_datetime("Publish", &self.publish_at, tz)?;
+        if publish_at <= Utc::now() {
+            return Err("Scheduled publish time must be in the future.".into());
+        }
+        Ok(Some(publish_at))
+    }
+
+    /// New date for the event, which can optionally be set when cancelling or postponing it.
+    fn rescheduled_at(&self, tz: Tz) -> Result<Option<DateTime<Utc>>, String> {
+        match self.status {
+            EventStatus::Cancelled | EventStatus::Postponed => {
+                parse_optional_datetime("Rescheduled", &self.rescheduled_at, tz)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Process the form to RSVP to an event, or take back an RSVP.
+async fn rsvp_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+    Form(form): Form<Rsvp>,
+) -> AppResult<Response> {
+    let Some(user) = current_user(&state, &cookies).await? else {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    if event.status != EventStatus::Published {
+        return Ok((StatusCode::BAD_REQUEST, "This event isn't taking RSVPs.").into_response());
+    }
+
+    match form.going {
+        true => state.db.create_rsvp(event.id, user.id).await?,
+        false => state.db.delete_rsvp(event.id, user.id).await?,
+    }
+    Ok(Redirect::to(&format!("/e/{}", event.id)).into_response())
+}
+#[derive(serde::Deserialize)]
+struct Rsvp {
+    going: bool,
+}
+
+/// Create a draft copy of an event, and go to it for editing.
+async fn duplicate_event_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+) -> AppResult<Response> {
+    let Some(editor) = current_user(&state, &cookies).await?.filter(|u| u.organizer) else {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let tags = state.db.lookup_tags_by_event_id(event.id).await?;
+    let mut fields = EventFields::from_event(&event, tags)?;
+    fields.title = format!("{} (copy)", fields.title);
+    fields.slug.clear();
+    // New events start out as drafts, so the copy can't go out before its details are fixed up.
+    let new_id = state.db.create_event(&fields, Some(editor.id)).await?;
+    jobs::schedule_event_emails(&state, new_id).await?;
+    Ok(Redirect::to(&format!("/e/{new_id}/edit")).into_response())
+}
+
+/// Display every revision of an event, with what changed in each one.
+async fn event_history_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let revisions = state.db.get_revisions(RevisionKind::Event, event.id).await?;
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("event", &event);
+    ctx.insert("history", &revision_history(&revisions)?);
+
+    let html = state.templates.render("event-history.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
+}
+
+/// Pair each revision with what changed in it, newest first.
+pub(crate) fn revision_history(revisions: &[Revision]) -> anyhow::Result<Vec<RevisionEntry<'_>>> {
+    let mut history = vec![];
+    for (i, revision) in revisions.iter().enumerate() {
+        let changes = revision.diff(i.checked_sub(1).map(|prev| &revisions[prev]))?;
+        history.push(RevisionEntry { revision, changes, current: i + 1 == revisions.len() });
+    }
+    history.reverse();
+    Ok(history)
+}
+
+/// A revision as shown on a history page.
+#[derive(serde::Serialize)]
+pub(crate) struct RevisionEntry<'a> {
+    revision: &'a Revision,
+    changes: Vec<FieldChange>,
+    /// Whether this is the record as it is now.
+    current: bool,
+}
+
+/// Put an event's details back the way they were in an earlier revision.
+///
+/// This is saved as a new revision, so a restore can itself be undone.
+async fn restore_event_revision_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path((event_id, revision_id)): Path<(i64, i64)>,
+) -> AppResult<Response> {
+    let Some(editor) = current_user(&state, &cookies).await?.filter(|u| u.organizer) else {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+    let Some(before) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let Some(revision) = state.db.lookup_revision(RevisionKind::Event, event_id, revision_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let mut fields: EventFields = serde_json::from_str(&revision.snapshot)?;
+    // Another event may have taken the old URL since, in which case keep the current one.
+    if fields.slug.is_empty() || state.db.is_event_slug_taken(&fields.slug, Some(before.id)).await? {
+        fields.slug = before.slug.clone();
+    }
+    state.db.update_event(before.id, &fields, Some(editor.id)).await?;
+    jobs::schedule_event_emails(&state, before.id).await?;
+    jobs::notify_event_changed(&state, &before).await?;
+    Ok(Redirect::to(&format!("/e/{event_id}/history")).into_response())
+}
+
+/// Save an event's details as a template for creating new events.
+async fn create_event_template_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+    Form(form): Form<CreateEventTemplate>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Ok((StatusCode::BAD_REQUEST, "Template name is required.").into_response());
+    }
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let tags = state.db.lookup_tags_by_event_id(event.id).await?;
+    let template_id = state
+        .db
+        .create_event_template(name, &EventFields::from_event(&event, tags)?)
+        .await?;
+    Ok(Redirect::to(&format!("/e/new?template={template_id}")).into_response())
+}
+#[derive(serde::Deserialize)]
+struct CreateEventTemplate {
+    name: String,
+}
+
+/// Remove a saved event template.
+async fn delete_event_template_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(template_id): Path<i64>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    state.db.delete_event_template(template_id).await?;
+    Ok(Redirect::to("/e/new").into_response())
 }
 
 /// Delete an event.
+///
+/// If anyone is planning on coming or has bought tickets, the event is
+/// cancelled instead, so they get notified and it doesn't just disappear on them.
 async fn delete_event(
     State(state): State<SharedAppState>,
-    Path(event_id): Path<String>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
 ) -> AppResult<impl IntoResponse> {
-    state.db.delete_event(event_id.parse().unwrap()).await?;
-    Ok("Event deleted.")
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if !state.db.get_rsvp_users(event.id).await?.is_empty() || state.db.event_has_orders(event.id).await? {
+        if event.status != EventStatus::Cancelled {
+            state
+                .db
+                .update_event_status(event.id, EventStatus::Cancelled, None, None)
+                .await?;
+            jobs::notify_event_changed(&state, &event).await?;
+        }
+        return Ok("Event has attendees, so it was cancelled instead.".into_response());
+    }
+
+    let photos = state.db.get_photos(event.id, true).await?;
+    state.db.delete_event(event.id).await?;
+    for photo in &photos {
+        galleries::remove_photo_files(&state, photo).await;
+    }
+    Ok("Event deleted.".into_response())
+}
+
+/// Form fields shared by the create and update event forms.
+///
+/// Times are submitted from `datetime-local` inputs, which carry no offset,
+/// so they're interpreted as wall-clock times in the selected `timezone`.
+#[derive(serde::Deserialize)]
+struct EventForm {
+    /// Empty to generate one from the date and title.
+    #[serde(default)]
+    slug: String,
+    title: String,
+    artist: String,
+    description: String,
+    #[serde(default)]
+    venue: String,
+    /// Comma-separated list of tags.
+    #[serde(default)]
+    tags: String,
+    timezone: String,
+    #[serde(default)]
+    doors_at: String,
+    start_at: String,
+    #[serde(default)]
+    end_at: String,
+}
+
+impl EventForm {
+    /// Parse and validate the submitted fields, returning a user-facing message on error.
+    fn parse(self) -> Result<EventFields, String> {
+        let title = self.title.trim();
+        if title.is_empty() {
+            return Err("Event title is required.".into());
+        }
+
+        let timezone: Tz = self
+            .timezone
+            .parse()
+            .map_err(|_| format!("Unknown timezone `{}`.", self.timezone))?;
+
+        let doors_at = parse_optional_datetime("Doors", &self.doors_at, timezone)?;
+        let start_at = parse_datetime("Start", &self.start_at, timezone)?;
+        let end_at = parse_optional_datetime("End", &self.end_at, timezone)?;
+
+        if doors_at.is_some_and(|doors_at| doors_at > start_at) {
+            return Err("Doors must open before the event starts.".into());
+        }
+        if end_at.is_some_and(|end_at| end_at <= start_at) {
+            return Err("The event must end after it starts.".into());
+        }
+
+        let slug = self.slug.trim();
+        if !slug.is_empty() {
+            slug::validate(slug, &RESERVED_SLUGS)?;
+        }
+
+        let mut tags: Vec<String> = vec![];
+        for tag in self.tags.split(',').map(str::trim).filter(|tag| !tag.is_empty()) {
+            if !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                tags.push(tag.to_string());
+            }
+        }
+
+        Ok(EventFields {
+            slug: slug.to_string(),
+            title: title.to_string(),
+            artist: self.artist.trim().to_string(),
+            description: self.description,
+            venue: self.venue.trim().to_string(),
+            tags,
+            timezone,
+            doors_at,
+            start_at,
+            end_at,
+        })
+    }
+}
+
+/// Parse a `datetime-local` input as a wall-clock time in `tz`, and convert it to UTC.
+pub(crate) fn parse_datetime(name: &str, value: &str, tz: Tz) -> Result<DateTime<Utc>, String> {
+    let value = value.trim();
+    let naive = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+        .map_err(|_| format!("{name} time `{value}` is not a valid date and time."))?;
+
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(date) => Ok(date.to_utc()),
+        // When the clocks go back, the same wall-clock time happens twice. Pick the first one.
+        LocalResult::Ambiguous(earliest, _) => Ok(earliest.to_utc()),
+        LocalResult::None => {
+            Err(format!("{name} time `{value}` doesn't exist in {tz}, due to daylight saving."))
+        }
+    }
+}
+
+/// Like [`parse_datetime`], but an empty input is treated as `None`.
+fn parse_optional_datetime(name: &str, value: &str, tz: Tz) -> Result<Option<DateTime<Utc>>, String> {
+    match value.trim() {
+        "" => Ok(None),
+        value => parse_datetime(name, value, tz).map(Some),
+    }
+}
+
+/// Names of all known IANA timezones, for the timezone picker.
+fn timezone_names() -> Vec<&'static str> {
+    TZ_VARIANTS.iter().map(|tz| tz.name()).collect()
+}
+
+/// Midnight at the start of `date` in `tz`, in UTC.
+pub(crate) fn start_of_day(date: NaiveDate, tz: Tz) -> DateTime<Utc> {
+    match tz.from_local_datetime(&date.and_time(NaiveTime::MIN)) {
+        LocalResult::Single(date) | LocalResult::Ambiguous(date, _) => date.to_utc(),
+        // Some zones skip midnight for daylight saving, so fall back to treating it as UTC.
+        LocalResult::None => date.and_time(NaiveTime::MIN).and_utc(),
+    }
+}
+
+/// Deserialize an empty string as `None`, for optional inputs in HTML forms.
+pub(crate) fn empty_as_none<'de, D, T>(de: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    match Option::<String>::deserialize(de)?.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(value) => T::deserialize(value.into_deserializer()).map(Some),
+    }
+}
+
+/// Like [`empty_as_none`], but parses the value with [`FromStr`], for numbers in query strings.
+pub(crate) fn empty_as_none_parsed<'de, D, T>(de: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match Option::<String>::deserialize(de)?.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(value) => value.parse().map(Some).map_err(serde::de::Error::custom),
+    }
 }
@@ -0,0 +1,196 @@
+//! Atom and RSS feeds of posts and upcoming events.
+//!
+//! Feeds are polled constantly, so they support conditional requests, and
+//! readers which already have the latest version just get `304 Not Modified`.
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use sha2::{Digest, Sha256};
+
+use crate::app::posts::render_body;
+use crate::utils::db::{Event, EventSearch, EventSort, EventStatus, Post, PostSearch};
+use crate::utils::feed::{self, escape, Feed, FeedEntry};
+use crate::utils::types::{AppResult, AppRouter, AppState, SharedAppState};
+
+/// Add all `feeds` routes to the router.
+pub fn register_routes(router: AppRouter) -> AppRouter {
+    router
+        .route("/feed.xml", get(posts_atom))
+        .route("/rss.xml", get(posts_rss))
+        .route("/events/feed.xml", get(events_atom))
+        .route("/events/rss.xml", get(events_rss))
+}
+
+/// Most posts included in a feed.
+const POSTS_LIMIT: u32 = 20;
+/// Most events included in a feed.
+const EVENTS_LIMIT: u32 = 50;
+
+const ATOM: &str = "application/atom+xml; charset=utf-8";
+const RSS: &str = "application/rss+xml; charset=utf-8";
+
+async fn posts_atom(State(state): State<SharedAppState>, headers: HeaderMap) -> AppResult<Response> {
+    let posts = latest_posts(&state).await?;
+    let feed = posts_feed(&state, &posts, "/feed.xml").await?;
+    Ok(conditional(&headers, ATOM, feed.updated, feed::atom(&feed)))
+}
+
+async fn posts_rss(State(state): State<SharedAppState>, headers: HeaderMap) -> AppResult<Response> {
+    let posts = latest_posts(&state).await?;
+    let feed = posts_feed(&state, &posts, "/rss.xml").await?;
+    Ok(conditional(&headers, RSS, feed.updated, feed::rss(&feed)))
+}
+
+async fn events_atom(State(state): State<SharedAppState>, headers: HeaderMap) -> AppResult<Response> {
+    let events = upcoming_events(&state).await?;
+    let feed = events_feed(&state, &events, "/events/feed.xml");
+    Ok(conditional(&headers, ATOM, feed.updated, feed::atom(&feed)))
+}
+
+async fn events_rss(State(state): State<SharedAppState>, headers: HeaderMap) -> AppResult<Response> {
+    let events = upcoming_events(&state).await?;
+    let feed = events_feed(&state, &events, "/events/rss.xml");
+    Ok(conditional(&headers, RSS, feed.updated, feed::rss(&feed)))
+}
+
+async fn latest_posts(state: &AppState) -> anyhow::Result<Vec<Post>> {
+    let search = PostSearch { limit: POSTS_LIMIT, ..Default::default() };
+    Ok(state.db.search_posts(&search).await?.posts)
+}
+
+/// Build a feed of posts, with their full rendered bodies.
+async fn posts_feed<'a>(state: &AppState, posts: &'a [Post], path: &str) -> anyhow::Result<Feed<'a>> {
+    let url = &state.config.app.url;
+    let mut entries = vec![];
+    for post in posts {
+        let created = post.created_at.to_utc();
+        entries.push(FeedEntry {
+            id: tag_uri(state, created, &format!("post-{}", post.id)),
+            title: &post.title,
+            link: format!("{url}/p/{}", post.slug),
+            author: post.byline(),
+            published: post.published_at.unwrap_or(created),
+            updated: post.updated_at.to_utc(),
+            content: render_body(state, post).await?,
+        });
+    }
+    Ok(Feed {
+        id: format!("{url}{path}"),
+        title: "WLSD: Posts",
+        link: format!("{url}/posts"),
+        self_link: format!("{url}{path}"),
+        updated: last_updated(&entries),
+        entries,
+    })
+}
+
+async fn upcoming_events(state: &AppState) -> anyhow::Result<Vec<Event>> {
+    let search = EventSearch {
+        query: None,
+        ends_after: Some(Utc::now()),
+        ends_before: None,
+        starts_after: None,
+        starts_before: None,
+        tag: None,
+        venue: None,
+        status: None,
+        include_private: false,
+        sort: EventSort::Soonest,
+        cursor: None,
+        limit: EVENTS_LIMIT,
+    };
+    Ok(state.db.search_events(&search).await?.events)
+}
+
+/// Build a feed of upcoming events, with their details and descriptions.
+fn events_feed<'a>(state: &AppState, events: &'a [Event], path: &str) -> Feed<'a> {
+    let url = &state.config.app.url;
+    let entries: Vec<FeedEntry> = events
+        .iter()
+        .map(|event| FeedEntry {
+            id: tag_uri(state, event.created_at.to_utc(), &format!("event-{}", event.id)),
+            title: &event.title,
+            link: format!("{url}/e/{}", event.slug),
+            author: event.artist.clone(),
+            published: event.created_at.to_utc(),
+            updated: event.updated_at.to_utc(),
+            content: event_content(event, event.timezone.parse().unwrap_or(state.config.app.timezone)),
+        })
+        .collect();
+    Feed {
+        id: format!("{url}{path}"),
+        title: "WLSD: Upcoming Events",
+        link: format!("{url}/events"),
+        self_link: format!("{url}{path}"),
+        updated: last_updated(&entries),
+        entries,
+    }
+}
+
+/// Describe an event as HTML, with the plain-text description escaped like on its page.
+fn event_content(event: &Event, tz: Tz) -> String {
+    let mut html = format!("<p>{}", event.start_at.with_timezone(&tz).format("%A, %B %-d, %Y, %-I:%M%P"));
+    if !event.venue.is_empty() {
+        html.push_str(&format!(" at {}", escape(&event.venue)));
+    }
+    html.push_str("</p>");
+    match event.status {
+        EventStatus::Cancelled => html.push_str("<p><strong>This event has been cancelled.</strong></p>"),
+        EventStatus::Postponed => html.push_str("<p><strong>This event has been postponed.</strong></p>"),
+        _ => {}
+    }
+    if !event.description.is_empty() {
+        html.push_str(&format!("<p>{}</p>", escape(&event.description).replace('\n', "<br>")));
+    }
+    html
+}
+
+/// Make a permanent ID for an entry, which survives changes to its URL.
+///
+/// See RFC 4151: `tag:<host>,<date the record was created>:<name>`.
+fn tag_uri(state: &AppState, created: DateTime<Utc>, name: &str) -> String {
+    let host = state.config.app.url.split("://").last().unwrap_or_default();
+    let host = host.split(':').next().unwrap_or_default();
+    format!("tag:{host},{}:{name}", created.format("%Y-%m-%d"))
+}
+
+/// When the newest change to any entry was made.
+fn last_updated(entries: &[FeedEntry]) -> DateTime<Utc> {
+    entries.iter().map(|e| e.updated).max().unwrap_or(DateTime::UNIX_EPOCH)
+}
+
+/// Serve a feed, or `304 Not Modified` if the reader already has this version of it.
+fn conditional(
+    headers: &HeaderMap,
+    content_type: &'static str,
+    updated: DateTime<Utc>,
+    body: String,
+) -> Response {
+    let etag = format!("\"{}\"", &hex::encode(Sha256::digest(body.as_bytes()))[..32]);
+    let last_modified = updated.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+    // If-None-Match wins if both are sent, since ETags are more precise.
+    let fresh = match headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+        Some(tags) => tags.split(',').any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        }),
+        None => headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+            .is_some_and(|since| updated.timestamp() <= since.timestamp()),
+    };
+
+    let cache = [(header::ETAG, etag), (header::LAST_MODIFIED, last_modified)];
+    if fresh {
+        return (StatusCode::NOT_MODIFIED, cache).into_response();
+    }
+    (cache, [(header::CONTENT_TYPE, content_type)], body).into_response()
+}
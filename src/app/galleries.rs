@@ -0,0 +1,360 @@
+//! Post-event photo galleries.
+//!
+//! Photos are stored on disk under `{uploads}/photos/`, as the original
+//! upload plus a JPEG thumbnail. They're served through handlers rather than
+//! as static files, so hidden photos and unpublished events stay private.
+
+use std::io::{Cursor, Write as _};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{DefaultBodyLimit, Multipart, Path, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form,
+};
+use axum_extra::extract::CookieJar;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::app::auth::{current_user, is_organizer};
+use crate::utils::db::{Event, Photo};
+use crate::utils::types::{AppResult, AppRouter, AppState, SharedAppState};
+
+/// Largest request accepted when uploading photos, across all files.
+const MAX_UPLOAD_BYTES: usize = 512 * 1024 * 1024;
+/// Thumbnails fit within a square of this many pixels.
+const THUMBNAIL_SIZE: u32 = 480;
+
+/// Add all `galleries` routes to the router.
+pub fn register_routes(router: AppRouter) -> AppRouter {
+    router
+        .route(
+            "/e/:event_id/photos",
+            get(gallery_page)
+                .post(upload_photos_form)
+                .layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES)),
+        )
+        .route("/e/:event_id/photos.zip", get(download_zip))
+        .route("/e/:event_id/photos/:photo_id/hide", post(hide_photo_form))
+        .route("/e/:event_id/photos/:photo_id/cover", post(cover_photo_form))
+        .route("/e/:event_id/photos/:photo_id/delete", post(delete_photo_form))
+        .route("/photos/:photo_id", get(photo_file))
+        .route("/photos/:photo_id/thumb", get(thumbnail_file))
+}
+
+/// Display an event's photos.
+///
+/// Organizers also see hidden photos, and get forms to manage them.
+async fn gallery_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+) -> AppResult<Response> {
+    render_gallery(&state, &cookies, event_id, &[]).await
+}
+
+/// Process the form to upload any number of photos at once.
+///
+/// Files which aren't images are skipped and reported, rather than failing the whole upload.
+async fn upload_photos_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+    mut multipart: Multipart,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let mut errors = vec![];
+    while let Some(field) = multipart.next_field().await? {
+        if field.name() != Some("photos") {
+            continue;
+        }
+        let filename = field.file_name().unwrap_or("photo").to_string();
+        let bytes = field.bytes().await?;
+        // Submitting the form without picking any files sends a single empty one.
+        if bytes.is_empty() {
+            continue;
+        }
+
+        let image = match tokio::task::spawn_blocking(move || process_image(bytes.to_vec())).await? {
+            Ok(image) => image,
+            Err(err) => {
+                tracing::warn!("galleries: skipping upload filename={filename}: {err:#}");
+                errors.push(format!("{filename} isn't a supported image."));
+                continue;
+            }
+        };
+        save_photo(&state, &event, &filename, image).await?;
+    }
+
+    if !errors.is_empty() {
+        return render_gallery(&state, &cookies, event_id, &errors).await;
+    }
+    Ok(Redirect::to(&format!("/e/{event_id}/photos")).into_response())
+}
+
+/// Hide a photo from the public, or show it again.
+async fn hide_photo_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path((event_id, photo_id)): Path<(i64, i64)>,
+    Form(form): Form<HidePhoto>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    state.db.set_photo_hidden(event_id, photo_id, form.hidden).await?;
+    Ok(Redirect::to(&format!("/e/{event_id}/photos")).into_response())
+}
+#[derive(serde::Deserialize)]
+struct HidePhoto {
+    hidden: bool,
+}
+
+/// Show a photo at the top of the event page, or stop showing it.
+async fn cover_photo_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path((event_id, photo_id)): Path<(i64, i64)>,
+    Form(form): Form<CoverPhoto>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    let photo_id = form.cover.then_some(photo_id);
+    state.db.set_event_cover(event_id, photo_id).await?;
+    Ok(Redirect::to(&format!("/e/{event_id}/photos")).into_response())
+}
+#[derive(serde::Deserialize)]
+struct CoverPhoto {
+    cover: bool,
+}
+
+/// Remove a photo for good.
+async fn delete_photo_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path((event_id, photo_id)): Path<(i64, i64)>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    if let Some(photo) = state.db.delete_photo(event_id, photo_id).await? {
+        remove_photo_files(&state, &photo).await;
+    }
+    Ok(Redirect::to(&format!("/e/{event_id}/photos")).into_response())
+}
+
+/// Download all of an event's visible photos as a zip file.
+async fn download_zip(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+) -> AppResult<Response> {
+    let Some(event) = visible_event(&state, &cookies, event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let photos = state.db.get_photos(event.id, false).await?;
+    let files: Vec<_> = photos
+        .iter()
+        .enumerate()
+        .map(|(i, photo)| (format!("{:03}.{}", i + 1, photo.format), photo_path(&state, photo)))
+        .collect();
+
+    // Photos are already compressed, so they're stored as-is rather than deflated again.
+    let zip = tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        for (name, path) in files {
+            let bytes = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+            zip.start_file(name, options)?;
+            zip.write_all(&bytes)?;
+        }
+        Ok(zip.finish()?.into_inner())
+    })
+    .await??;
+
+    let headers = [
+        (header::CONTENT_TYPE, "application/zip".to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"photos-{event_id}.zip\""),
+        ),
+    ];
+    Ok((headers, zip).into_response())
+}
+
+/// Serve the original image file of a photo.
+async fn photo_file(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(photo_id): Path<i64>,
+) -> AppResult<Response> {
+    let Some(photo) = visible_photo(&state, &cookies, photo_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let content_type =
+        ImageFormat::from_extension(&photo.format).map_or("application/octet-stream", |f| f.to_mime_type());
+    let bytes = tokio::fs::read(photo_path(&state, &photo)).await?;
+    Ok(([(header::CONTENT_TYPE, content_type)], bytes).into_response())
+}
+
+/// Serve the thumbnail of a photo.
+async fn thumbnail_file(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(photo_id): Path<i64>,
+) -> AppResult<Response> {
+    let Some(photo) = visible_photo(&state, &cookies, photo_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let bytes = tokio::fs::read(thumbnail_path(&state, &photo)).await?;
+    Ok(([(header::CONTENT_TYPE, "image/jpeg")], bytes).into_response())
+}
+
+/// Remove a photo's files from disk, after it's been removed from the database.
+///
+/// Failures are only logged, since the photo is already gone as far as anyone can tell.
+pub async fn remove_photo_files(state: &AppState, photo: &Photo) {
+    for path in [photo_path(state, photo), thumbnail_path(state, photo)] {
+        if let Err(err) = tokio::fs::remove_file(&path).await {
+            tracing::warn!("galleries: removing {}: {err}", path.display());
+        }
+    }
+}
+
+/// An uploaded image, ready to be saved.
+struct ProcessedImage {
+    bytes: Vec<u8>,
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    thumbnail: Vec<u8>,
+}
+
+/// Check that an upload is an image, and generate its thumbnail.
+///
+/// This is CPU-heavy, so run it on a blocking thread.
+fn process_image(bytes: Vec<u8>) -> Result<ProcessedImage> {
+    let reader = ImageReader::new(Cursor::new(&bytes)).with_guessed_format()?;
+    let format = reader.format().context("unknown image format")?;
+    let mut decoder = reader.into_decoder()?;
+    // Phones store photos sideways and say which way is up in the EXIF data.
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    let mut thumbnail = Cursor::new(vec![]);
+    DynamicImage::ImageRgb8(image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgb8())
+        .write_to(&mut thumbnail, ImageFormat::Jpeg)?;
+
+    Ok(ProcessedImage {
+        bytes,
+        format,
+        width: image.width(),
+        height: image.height(),
+        thumbnail: thumbnail.into_inner(),
+    })
+}
+
+/// Record a photo in the database, and write its files to disk.
+async fn save_photo(state: &AppState, event: &Event, filename: &str, image: ProcessedImage) -> Result<()> {
+    let format = image.format.extensions_str().first().copied().unwrap_or("img");
+    let id = state
+        .db
+        .create_photo(event.id, filename, format, image.width, image.height)
+        .await?;
+    let Some(photo) = state.db.lookup_photo_by_id(id).await? else {
+        anyhow::bail!("photo_id={id} disappeared after upload");
+    };
+
+    let written = async {
+        tokio::fs::create_dir_all(photos_dir(state)).await?;
+        tokio::fs::write(photo_path(state, &photo), &image.bytes).await?;
+        tokio::fs::write(thumbnail_path(state, &photo), &image.thumbnail).await?;
+        anyhow::Ok(())
+    }
+    .await;
+    // Don't leave a broken photo in the gallery.
+    if let Err(err) = written {
+        state.db.delete_photo(event.id, photo.id).await?;
+        remove_photo_files(state, &photo).await;
+        return Err(err.context(format!("saving photo_id={}", photo.id)));
+    }
+    Ok(())
+}
+
+fn photos_dir(state: &AppState) -> PathBuf {
+    state.config.app.uploads.join("photos")
+}
+
+fn photo_path(state: &AppState, photo: &Photo) -> PathBuf {
+    photos_dir(state).join(format!("{}.{}", photo.id, photo.format))
+}
+
+fn thumbnail_path(state: &AppState, photo: &Photo) -> PathBuf {
+    photos_dir(state).join(format!("{}-thumb.jpg", photo.id))
+}
+
+/// Look up an event, or `None` if the current user isn't allowed to see it.
+async fn visible_event(state: &AppState, cookies: &CookieJar, event_id: i64) -> Result<Option<Event>> {
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(None);
+    };
+    // Unpublished events don't exist as far as the public is concerned.
+    if !event.status.is_public() && !is_organizer(state, cookies).await? {
+        return Ok(None);
+    }
+    Ok(Some(event))
+}
+
+/// Look up a photo, or `None` if the current user isn't allowed to see it.
+async fn visible_photo(state: &AppState, cookies: &CookieJar, photo_id: i64) -> Result<Option<Photo>> {
+    let Some(photo) = state.db.lookup_photo_by_id(photo_id).await? else {
+        return Ok(None);
+    };
+    if visible_event(state, cookies, photo.event_id).await?.is_none() {
+        return Ok(None);
+    }
+    if photo.hidden && !is_organizer(state, cookies).await? {
+        return Ok(None);
+    }
+    Ok(Some(photo))
+}
+
+async fn render_gallery(
+    state: &AppState,
+    cookies: &CookieJar,
+    event_id: i64,
+    errors: &[String],
+) -> AppResult<Response> {
+    let user = current_user(state, cookies).await?;
+    let organizer = user.as_ref().is_some_and(|u| u.organizer);
+    let Some(event) = visible_event(state, cookies, event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("event", &event);
+    ctx.insert("photos", &state.db.get_photos(event.id, organizer).await?);
+    ctx.insert("organizer", &organizer);
+    ctx.insert("errors", errors);
+
+    let html = state.templates.render("gallery.tera.html", &ctx).unwrap();
+    let status = match errors {
+        [] => StatusCode::OK,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    Ok((status, Html(html)).into_response())
+}
@@ -0,0 +1,251 @@
+//! Guest lists, and the door check-in view.
+//!
+//! Organizers give artists and promoters an allocation of guest list spots
+//! for an event. Each allocation has a private link, which the owner can use
+//! to fill in names up to their quota without needing an account.
+//!
+//! At the door, the guest lists are merged with RSVPs into a single list
+//! for checking people in.
+
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form,
+};
+use axum_extra::extract::CookieJar;
+
+use crate::app::auth::is_organizer;
+use crate::utils::db::GuestAllocation;
+use crate::utils::types::{AppResult, AppRouter, AppState, SharedAppState};
+
+/// Add all `guestlists` routes to the router.
+pub fn register_routes(router: AppRouter) -> AppRouter {
+    router
+        .route("/e/:event_id/guestlist", post(create_allocation_form))
+        .route("/e/:event_id/guestlist/:allocation_id/delete", post(delete_allocation_form))
+        .route("/e/:event_id/guestlist.csv", get(export_csv))
+        .route("/e/:event_id/door", get(door_page).post(check_in_form))
+        .route("/guestlist/:token", get(guestlist_page).post(add_guest_form))
+        .route("/guestlist/:token/:guest_id/delete", post(delete_guest_form))
+}
+
+/// Process the form to give someone a guest list allocation for an event.
+async fn create_allocation_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+    Form(form): Form<CreateAllocation>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Ok((StatusCode::BAD_REQUEST, "Allocation name is required.").into_response());
+    }
+
+    state.db.create_guest_allocation(event.id, name, form.quota.into()).await?;
+    Ok(Redirect::to(&format!("/e/{}/edit", event.id)).into_response())
+}
+#[derive(serde::Deserialize)]
+struct CreateAllocation {
+    name: String,
+    quota: u16,
+}
+
+/// Take away a guest list allocation, along with everyone on it.
+async fn delete_allocation_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path((event_id, allocation_id)): Path<(i64, i64)>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    state.db.delete_guest_allocation(event_id, allocation_id).await?;
+    Ok(Redirect::to(&format!("/e/{event_id}/edit")).into_response())
+}
+
+/// Display the door list, for checking people in.
+async fn door_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let entries = state.db.get_door_entries(event.id).await?;
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("event", &event);
+    ctx.insert("entries", &entries);
+    ctx.insert("expected", &entries.iter().map(|e| 1 + e.plus_ones).sum::<i64>());
+    ctx.insert(
+        "checked_in",
+        &entries
+            .iter()
+            .filter(|e| e.checked_in_at.is_some())
+            .map(|e| 1 + e.plus_ones)
+            .sum::<i64>(),
+    );
+
+    let html = state.templates.render("door.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
+}
+
+/// Process the form to check someone in at the door, or undo a check in.
+async fn check_in_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+    Form(form): Form<CheckIn>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    match form.kind.as_str() {
+        "rsvp" => state.db.check_in_rsvp(event_id, form.id, form.checked_in).await?,
+        "guest" => state.db.check_in_guest(event_id, form.id, form.checked_in).await?,
+        _ => return Ok(StatusCode::BAD_REQUEST.into_response()),
+    }
+    Ok(Redirect::to(&format!("/e/{event_id}/door")).into_response())
+}
+#[derive(serde::Deserialize)]
+struct CheckIn {
+    kind: String,
+    id: i64,
+    checked_in: bool,
+}
+
+/// Download the door list as a CSV file.
+async fn export_csv(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    let mut csv = csv::Writer::from_writer(vec![]);
+    csv.write_record(["Name", "Type", "List", "Plus Ones", "Checked In"])?;
+    for entry in state.db.get_door_entries(event_id).await? {
+        csv.write_record([
+            spreadsheet_text(entry.name),
+            entry.kind,
+            spreadsheet_text(entry.list.unwrap_or_default()),
+            entry.plus_ones.to_string(),
+            entry.checked_in_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+        ])?;
+    }
+    let csv = csv.into_inner()?;
+
+    let headers = [
+        (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"guestlist-{event_id}.csv\""),
+        ),
+    ];
+    Ok((headers, csv).into_response())
+}
+
+/// Make sure a CSV cell is read as text by spreadsheets, which run cells starting
+/// with `=` and the like as formulas. Guest names come from anyone with a private link.
+fn spreadsheet_text(cell: String) -> String {
+    if cell.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{cell}")
+    } else {
+        cell
+    }
+}
+
+/// Display an allocation owner's guest list, from their private link.
+async fn guestlist_page(
+    State(state): State<SharedAppState>,
+    Path(token): Path<String>,
+) -> AppResult<Response> {
+    let Some(allocation) = state.db.lookup_guest_allocation_by_token(&token).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    render_guestlist(&state, allocation, None).await
+}
+
+/// Process the form to add someone to a guest list.
+async fn add_guest_form(
+    State(state): State<SharedAppState>,
+    Path(token): Path<String>,
+    Form(form): Form<AddGuest>,
+) -> AppResult<Response> {
+    let Some(allocation) = state.db.lookup_guest_allocation_by_token(&token).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let name = form.name.trim();
+    let error = if name.is_empty() {
+        Some("Please enter a name.")
+    } else if !state.db.create_guest(allocation.id, name, form.plus_ones.into()).await? {
+        Some("There aren't enough spots left on your list for that.")
+    } else {
+        None
+    };
+
+    match error {
+        Some(error) => render_guestlist(&state, allocation, Some(error)).await,
+        None => Ok(Redirect::to(&format!("/guestlist/{token}")).into_response()),
+    }
+}
+#[derive(serde::Deserialize)]
+struct AddGuest {
+    name: String,
+    #[serde(default)]
+    plus_ones: u8,
+}
+
+/// Remove someone from a guest list.
+async fn delete_guest_form(
+    State(state): State<SharedAppState>,
+    Path((token, guest_id)): Path<(String, i64)>,
+) -> AppResult<Response> {
+    let Some(allocation) = state.db.lookup_guest_allocation_by_token(&token).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    state.db.delete_guest(allocation.id, guest_id).await?;
+    Ok(Redirect::to(&format!("/guestlist/{token}")).into_response())
+}
+
+async fn render_guestlist(
+    state: &AppState,
+    allocation: GuestAllocation,
+    error: Option<&str>,
+) -> AppResult<Response> {
+    let Some(event) = state.db.lookup_event_by_event_id(&allocation.event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let guests = state.db.get_guests(allocation.id).await?;
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("event", &event);
+    ctx.insert("allocation", &allocation);
+    ctx.insert("guests", &guests);
+    ctx.insert("error", &error);
+
+    let html = state.templates.render("guestlist.tera.html", &ctx).unwrap();
+    let status = match error {
+        Some(_) => StatusCode::BAD_REQUEST,
+        None => StatusCode::OK,
+    };
+    Ok((status, Html(html)).into_response())
+}
@@ -6,8 +6,13 @@ use axum::{
 };
 use axum_extra::extract::CookieJar;
 
+use crate::app::posts::with_excerpts;
+use crate::utils::db::PostSearch;
 use crate::utils::types::{AppResult, AppRouter, SharedAppState};
 
+/// Number of recent posts shown on the front page.
+const RECENT_POSTS: u32 = 3;
+
 /// Add all `home` routes to the router.
 pub fn register_routes(router: AppRouter) -> AppRouter {
     router.route("/", get(home_page))
@@ -18,6 +23,12 @@ async fn home_page(State(state): State<SharedAppState>, cookies: CookieJar) -> A
     let mut ctx = tera::Context::new();
     ctx.insert("message", "Hello, world!");
 
+    let recent = state
+        .db
+        .search_posts(&PostSearch { limit: RECENT_POSTS, ..Default::default() })
+        .await?;
+    ctx.insert("posts", &with_excerpts(&recent.posts));
+
     if let Some(session_token) = cookies.get("session") {
         let Some(user) = state.db.lookup_user_from_session_token(session_token.value()).await? else {
             return Ok(StatusCode::FORBIDDEN.into_response());
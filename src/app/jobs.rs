@@ -0,0 +1,507 @@
+//! A persistent background job scheduler.
+//!
+//! Jobs are stored in the `jobs` table, so they survive restarts. A single
+//! worker task polls for jobs which are due and runs them one at a time.
+//!
+//! # Delivery
+//!
+//! Failed jobs are retried with a backoff. Since a job might send emails to
+//! many recipients before failing, each email is recorded with
+//! [`Db::mark_email_sent`] before it goes out, and skipped if it was already
+//! sent. This means an email may be lost if the server dies at exactly the
+//! wrong moment, but it will never be sent twice.
+//!
+//! [`Db::mark_email_sent`]: crate::utils::db::Db::mark_email_sent
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use chrono_tz::Tz;
+use lettre::message::{
+    header::{HeaderName, HeaderValue},
+    MultiPart,
+};
+
+use crate::app::posts::render_body;
+use crate::utils::db::{
+    CommentStatus, Event, EventStatus, Job, NewsletterRecipient, Post, PostStatus, RecipientStatus,
+};
+use crate::utils::types::{AppState, SharedAppState};
+use crate::utils::webhooks;
+
+/// How often to check for jobs which are due.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// How many times to try a job before giving up on it.
+const MAX_ATTEMPTS: i64 = 5;
+
+/// How many hours before an event starts to remind attendees.
+const REMINDER_HOURS: [i64; 2] = [24, 2];
+/// How many hours after an event ends to send a follow-up.
+const FOLLOWUP_HOURS: i64 = 12;
+
+/// How many newsletter emails to send at a time.
+const NEWSLETTER_BATCH_SIZE: u32 = 50;
+/// How long to wait between batches of newsletter emails, to stay under the mail server's rate limits.
+const NEWSLETTER_BATCH_INTERVAL: TimeDelta = TimeDelta::minutes(1);
+
+/// A unit of background work.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum JobKind {
+    /// Remind attendees that an event is coming up.
+    EventReminder {
+        event_id: i64,
+        hours_before: i64,
+        /// When the event started at the time this was scheduled, so reminders
+        /// for a time which has since changed can be skipped.
+        start_at: DateTime<Utc>,
+    },
+    /// Thank attendees for coming, and point them at photos and feedback.
+    EventFollowup { event_id: i64, start_at: DateTime<Utc> },
+    /// Tell attendees that an event was changed or cancelled.
+    EventChanged {
+        event_id: i64,
+        /// Status of the event right after the change.
+        status: EventStatus,
+        changes: Vec<EventChange>,
+    },
+    /// Email the next batch of subscribers a newsletter.
+    Newsletter { newsletter_id: i64 },
+    /// Tell the owners of a post or event about a new comment on it.
+    NewComment { comment_id: i64 },
+    /// Send a webhook to one of the configured endpoints.
+    Webhook {
+        url: String,
+        /// JSON body, which is signed afresh on each attempt.
+        body: String,
+    },
+}
+
+/// A material change to an event, formatted for attendees.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct EventChange {
+    field: String,
+    before: String,
+    after: String,
+}
+
+/// Run the job worker forever.
+pub async fn run(state: SharedAppState) {
+    match state.db.reset_running_jobs().await {
+        Ok(0) => {}
+        Ok(n) => tracing::warn!("jobs: resuming {n} interrupted job(s)"),
+        Err(err) => tracing::error!("jobs: resetting interrupted jobs: {err:#}"),
+    }
+
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        loop {
+            match state.db.claim_next_job(Utc::now()).await {
+                Ok(Some(job)) => run_job(&state, job).await,
+                Ok(None) => break,
+                Err(err) => {
+                    tracing::error!("jobs: claiming next job: {err:#}");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Run a single job and record the outcome.
+async fn run_job(state: &AppState, job: Job) {
+    let result = match serde_json::from_str::<JobKind>(&job.payload) {
+        Ok(kind) => run_job_kind(state, &job.key, kind).await,
+        Err(err) => Err(err).context("parsing payload"),
+    };
+
+    let recorded = match result {
+        Ok(()) => {
+            tracing::info!("jobs: finished key={}", job.key);
+            state.db.complete_job(job.id).await
+        }
+        Err(err) => {
+            let retry_at = (job.attempts < MAX_ATTEMPTS)
+                .then(|| Utc::now() + TimeDelta::minutes(5 * job.attempts * job.attempts));
+            tracing::error!("jobs: key={} attempt={} failed: {err:#}", job.key, job.attempts);
+            state.db.fail_job(job.id, &format!("{err:#}"), retry_at).await
+        }
+    };
+    if let Err(err) = recorded {
+        tracing::error!("jobs: recording outcome of key={}: {err:#}", job.key);
+    }
+}
+
+async fn run_job_kind(state: &AppState, key: &str, kind: JobKind) -> Result<()> {
+    match kind {
+        JobKind::EventReminder { event_id, hours_before, start_at } => {
+            let Some(event) = current_event(state, event_id, start_at).await? else {
+                return Ok(());
+            };
+            // Don't bother reminding people about something that's already started.
+            if event.start_at <= Utc::now() {
+                return Ok(());
+            }
+
+            let subject = format!("Reminder: {} starts in {hours_before} hours", event.title);
+            let key = format!("event_reminder:{event_id}:{hours_before}:{}", start_at.timestamp());
+            let ctx = tera::Context::new();
+            send_to_attendees(state, &event, &key, &subject, "email-event-reminder.tera.txt", ctx).await
+        }
+        JobKind::EventFollowup { event_id, start_at } => {
+            let Some(event) = current_event(state, event_id, start_at).await? else {
+                return Ok(());
+            };
+
+            let subject = format!("Thanks for coming to {}", event.title);
+            let key = format!("event_followup:{event_id}:{}", start_at.timestamp());
+            let ctx = tera::Context::new();
+            send_to_attendees(state, &event, &key, &subject, "email-event-followup.tera.txt", ctx).await
+        }
+        JobKind::EventChanged { event_id, status, changes } => {
+            let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+                return Ok(());
+            };
+
+            let subject = match status {
+                EventStatus::Cancelled => format!("Cancelled: {}", event.title),
+                EventStatus::Postponed => format!("Postponed: {}", event.title),
+                _ => format!("Update: {} has changed", event.title),
+            };
+            let mut ctx = tera::Context::new();
+            ctx.insert("status", &status);
+            ctx.insert("changes", &changes);
+            send_to_attendees(state, &event, key, &subject, "email-event-changed.tera.txt", ctx).await
+        }
+        JobKind::Newsletter { newsletter_id } => send_newsletter_batch(state, newsletter_id).await,
+        JobKind::NewComment { comment_id } => send_comment_notifications(state, comment_id).await,
+        JobKind::Webhook { url, body } => {
+            // Endpoints removed from the config since don't want to hear about it.
+            let Some(webhook) = state.config.webhooks.iter().find(|w| w.url == url) else {
+                return Ok(());
+            };
+            webhooks::send(webhook, &body).await
+        }
+    }
+}
+
+/// Look up an event for an email job, or `None` if the job is no longer relevant.
+async fn current_event(state: &AppState, event_id: i64, start_at: DateTime<Utc>) -> Result<Option<Event>> {
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(None);
+    };
+    // Cancelled, postponed, or unpublished events don't get emails, and neither do
+    // events which were rescheduled, since that schedules new jobs for the new time.
+    if event.status != EventStatus::Published || event.start_at != start_at {
+        return Ok(None);
+    }
+    Ok(Some(event))
+}
+
+/// Email everyone who RSVP'd to or holds tickets for `event`, skipping anyone who already got the email identified by `key`.
+///
+/// The `template` is rendered with `ctx`, plus the `user`, `event`, and a few site-wide values.
+async fn send_to_attendees(
+    state: &AppState,
+    event: &Event,
+    key: &str,
+    subject: &str,
+    template: &str,
+    mut ctx: tera::Context,
+) -> Result<()> {
+    for user in state.db.get_event_attendees(event.id).await? {
+        if !state.db.mark_email_sent(&format!("{key}:{}", user.id)).await? {
+            continue;
+        }
+
+        ctx.insert("user", &user);
+        ctx.insert("event", event);
+        ctx.insert("url", &state.config.app.url);
+        ctx.insert("feedback_url", &state.config.app.feedback_url);
+        let body = state.templates.render(template, &ctx)?;
+
+        let msg = state.mail.builder().to(user.mailbox()?).subject(subject).body(body)?;
+        state
+            .mail
+            .send(msg)
+            .await
+            .with_context(|| format!("emailing user_id={}", user.id))?;
+    }
+    Ok(())
+}
+
+/// Email the next batch of a newsletter's recipients, then schedule the batch after that.
+///
+/// Each recipient's status is recorded as they're emailed, so a job which fails
+/// partway picks up where it left off when it's retried.
+async fn send_newsletter_batch(state: &AppState, newsletter_id: i64) -> Result<()> {
+    let Some(newsletter) = state.db.lookup_newsletter_by_id(newsletter_id).await? else {
+        return Ok(());
+    };
+    let Some(post) = state.db.lookup_post_by_id(newsletter.post_id).await? else {
+        return Ok(());
+    };
+    // Unpublishing a post pauses its newsletter, until it's resumed from the post's newsletter page.
+    if newsletter.finished_at.is_some() || post.status != PostStatus::Published {
+        return Ok(());
+    }
+
+    let body_html = absolute_links(&render_body(state, &post).await?, &state.config.app.url);
+    let recipients = state
+        .db
+        .claim_newsletter_recipients(newsletter.id, NEWSLETTER_BATCH_SIZE)
+        .await?;
+    for (i, recipient) in recipients.iter().enumerate() {
+        let (status, error) = match send_newsletter_email(state, &post, &body_html, recipient).await {
+            Ok(()) => (RecipientStatus::Sent, None),
+            // Bad addresses and full mailboxes won't fix themselves, so don't hold everyone else up.
+            Err(err) if is_permanent_failure(&err) => {
+                tracing::warn!("jobs: newsletter_id={newsletter_id} user_id={}: {err:#}", recipient.user_id);
+                (RecipientStatus::Failed, Some(format!("{err:#}")))
+            }
+            Err(err) => {
+                // Nobody from here on was emailed, so put them back to be retried.
+                for recipient in &recipients[i..] {
+                    state
+                        .db
+                        .set_newsletter_recipient_status(
+                            newsletter.id,
+                            recipient.user_id,
+                            RecipientStatus::Pending,
+                            None,
+                            Utc::now(),
+                        )
+                        .await?;
+                }
+                return Err(err.context(format!("emailing user_id={}", recipient.user_id)));
+            }
+        };
+        state
+            .db
+            .set_newsletter_recipient_status(
+                newsletter.id,
+                recipient.user_id,
+                status,
+                error.as_deref(),
+                Utc::now(),
+            )
+            .await?;
+    }
+
+    if recipients.len() < NEWSLETTER_BATCH_SIZE as usize {
+        return state.db.finish_newsletter(newsletter.id, Utc::now()).await;
+    }
+    schedule_newsletter(state, newsletter.id, Utc::now() + NEWSLETTER_BATCH_INTERVAL).await
+}
+
+/// Email the owners of a post or event about a new comment, unless it's been taken down since.
+async fn send_comment_notifications(state: &AppState, comment_id: i64) -> Result<()> {
+    let Some(comment) = state.db.lookup_comment_by_id(comment_id).await? else {
+        return Ok(());
+    };
+    if comment.deleted_at.is_some() || comment.status == CommentStatus::Hidden {
+        return Ok(());
+    }
+
+    let subject = format!("New comment on {}", comment.target_title);
+    for user in state.db.get_comment_target_owners(comment.target, comment.target_id).await? {
+        // Nobody needs to hear about their own comments.
+        if user.id == comment.user_id
+            || !state.db.mark_email_sent(&format!("comment:{comment_id}:{}", user.id)).await?
+        {
+            continue;
+        }
+
+        let mut ctx = tera::Context::new();
+        ctx.insert("user", &user);
+        ctx.insert("comment", &comment);
+        ctx.insert("url", &state.config.app.url);
+        let body = state.templates.render("email-comment.tera.txt", &ctx)?;
+
+        let msg = state.mail.builder().to(user.mailbox()?).subject(&subject).body(body)?;
+        state
+            .mail
+            .send(msg)
+            .await
+            .with_context(|| format!("emailing user_id={}", user.id))?;
+    }
+    Ok(())
+}
+
+/// Email a newsletter to one recipient, as HTML with a plain text alternative.
+async fn send_newsletter_email(
+    state: &AppState,
+    post: &Post,
+    body_html: &str,
+    recipient: &NewsletterRecipient,
+) -> Result<()> {
+    let url = &state.config.app.url;
+    let token = recipient.unsubscribe_token.as_deref().unwrap_or_default();
+    let unsubscribe_url = format!("{url}/newsletter/unsubscribe/{token}");
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("post", post);
+    ctx.insert("body_html", body_html);
+    // Markdown reads fine as plain text, once its links point back at the site.
+    ctx.insert("body_text", &post.body.replace("](/", &format!("]({url}/")));
+    ctx.insert("user", recipient);
+    ctx.insert("url", url);
+    ctx.insert("unsubscribe_url", &unsubscribe_url);
+    let html = state.templates.render("email-newsletter.tera.html", &ctx)?;
+    let text = state.templates.render("email-newsletter.tera.txt", &ctx)?;
+
+    let mut msg = state
+        .mail
+        .multipart_builder()
+        .to(recipient.mailbox()?)
+        .subject(&post.title)
+        .multipart(MultiPart::alternative_plain_html(text, html))?;
+    // Lets mail clients show their own unsubscribe button, which unsubscribes with one
+    // POST (RFC 8058), rather than leaving people to mark it as spam.
+    let headers = msg.headers_mut();
+    headers.insert_raw(HeaderValue::new(
+        HeaderName::new_from_ascii_str("List-Unsubscribe"),
+        format!("<{unsubscribe_url}>"),
+    ));
+    headers.insert_raw(HeaderValue::new(
+        HeaderName::new_from_ascii_str("List-Unsubscribe-Post"),
+        "List-Unsubscribe=One-Click".into(),
+    ));
+    state.mail.send(msg).await
+}
+
+/// Whether an email can never be sent, like to an invalid address, rather than failing
+/// for a reason that might go away, like the mail server being down.
+fn is_permanent_failure(err: &anyhow::Error) -> bool {
+    let refused = err
+        .downcast_ref::<lettre::transport::smtp::Error>()
+        .is_some_and(|err| err.is_permanent());
+    refused || err.is::<lettre::address::AddressError>()
+}
+
+/// Point links and images in rendered HTML back at the site, since emails are read elsewhere.
+fn absolute_links(html: &str, url: &str) -> String {
+    let mut html = html.to_string();
+    for attribute in ["href", "src"] {
+        html = html
+            .replace(&format!("{attribute}=\"//"), &format!("{attribute}=\"https://"))
+            .replace(&format!("{attribute}=\"/"), &format!("{attribute}=\"{url}/"));
+    }
+    html
+}
+
+/// Start, or resume, emailing a newsletter at `run_at`.
+pub async fn schedule_newsletter(state: &AppState, newsletter_id: i64, run_at: DateTime<Utc>) -> Result<()> {
+    let kind = JobKind::Newsletter { newsletter_id };
+    let key = format!("newsletter:{newsletter_id}:{}", run_at.timestamp_micros());
+    state.db.schedule_job(&key, &serde_json::to_string(&kind)?, run_at).await
+}
+
+/// Notify the owners of a post or event about a new comment on it.
+pub async fn notify_new_comment(state: &AppState, comment_id: i64) -> Result<()> {
+    let kind = JobKind::NewComment { comment_id };
+    let key = format!("new_comment:{comment_id}");
+    state.db.schedule_job(&key, &serde_json::to_string(&kind)?, Utc::now()).await
+}
+
+/// Tell every configured webhook endpoint that a post was published.
+pub async fn notify_post_published(state: &AppState, post: &Post) -> Result<()> {
+    let now = Utc::now();
+    let body = serde_json::json!({
+        "type": "post.published",
+        "created": now.timestamp(),
+        "data": {
+            "id": post.id,
+            "title": post.title,
+            "slug": post.slug,
+            "url": format!("{}/p/{}", state.config.app.url, post.slug),
+            "authors": post.byline(),
+            "published_at": post.published_at,
+        },
+    })
+    .to_string();
+
+    for webhook in &state.config.webhooks {
+        let kind = JobKind::Webhook { url: webhook.url.clone(), body: body.clone() };
+        let key = format!("webhook:post.published:{}:{}:{}", post.id, now.timestamp_micros(), webhook.url);
+        state.db.schedule_job(&key, &serde_json::to_string(&kind)?, now).await?;
+    }
+    Ok(())
+}
+
+/// Schedule reminder and follow-up emails for an event, based on its current times.
+///
+/// Call this whenever an event is created or its times change.
+pub async fn schedule_event_emails(state: &AppState, event_id: i64) -> Result<()> {
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(());
+    };
+    let start = event.start_at.timestamp();
+
+    for hours_before in REMINDER_HOURS {
+        let run_at = event.start_at - TimeDelta::hours(hours_before);
+        // A "starts in 24 hours" email is wrong if it goes out an hour beforehand.
+        if run_at <= Utc::now() {
+            continue;
+        }
+        let kind = JobKind::EventReminder { event_id, hours_before, start_at: event.start_at };
+        let key = format!("event_reminder:{event_id}:{hours_before}:{start}");
+        state.db.schedule_job(&key, &serde_json::to_string(&kind)?, run_at).await?;
+    }
+
+    let kind = JobKind::EventFollowup { event_id, start_at: event.start_at };
+    let key = format!("event_followup:{event_id}:{start}");
+    let run_at = event.end_at.unwrap_or(event.start_at) + TimeDelta::hours(FOLLOWUP_HOURS);
+    state.db.schedule_job(&key, &serde_json::to_string(&kind)?, run_at).await?;
+
+    Ok(())
+}
+
+/// Notify attendees if any material details of an event changed, compared to `before`.
+///
+/// Material details are the ones which affect whether or when people show up:
+/// the times, the venue, and whether it's still happening.
+pub async fn notify_event_changed(state: &AppState, before: &Event) -> Result<()> {
+    let Some(after) = state.db.lookup_event_by_event_id(&before.id).await? else {
+        return Ok(());
+    };
+    // Nobody outside of the organizers knows about unpublished events.
+    if !before.status.is_public() {
+        return Ok(());
+    }
+
+    let mut changes = vec![];
+    let mut compare = |field: &str, before: String, after: String| {
+        if before != after {
+            changes.push(EventChange { field: field.into(), before, after });
+        }
+    };
+    let time = |event: &Event, time: Option<DateTime<Utc>>| {
+        let tz: Tz = event.timezone.parse().unwrap_or(state.config.app.timezone);
+        match time {
+            Some(time) => time.with_timezone(&tz).format("%a %b %-d, %-I:%M%P %Z").to_string(),
+            None => "TBA".into(),
+        }
+    };
+    compare("Status", format!("{:?}", before.status), format!("{:?}", after.status));
+    compare("Doors", time(before, before.doors_at), time(&after, after.doors_at));
+    compare("Start", time(before, Some(before.start_at)), time(&after, Some(after.start_at)));
+    compare("End", time(before, before.end_at), time(&after, after.end_at));
+    compare("Venue", before.venue.clone(), after.venue.clone());
+    compare(
+        "New date",
+        time(before, before.rescheduled_at),
+        time(&after, after.rescheduled_at),
+    );
+
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let kind = JobKind::EventChanged { event_id: after.id, status: after.status, changes };
+    let key = format!("event_changed:{}:{}", after.id, now.timestamp_micros());
+    state.db.schedule_job(&key, &serde_json::to_string(&kind)?, now).await
+}
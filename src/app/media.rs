@@ -0,0 +1,285 @@
+//! Media library of images and other files, for use in posts.
+//!
+//! Files are stored on disk under `{uploads}/media/`, named by ID, and
+//! served at `/media/{id}/{filename}`. Unlike gallery photos they're always
+//! public, since they're meant to be linked to from anywhere.
+
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use axum::{
+    extract::{DefaultBodyLimit, Multipart, Path, Query, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form,
+};
+use axum_extra::extract::CookieJar;
+use image::{metadata::Orientation, ImageDecoder, ImageFormat, ImageReader};
+
+use crate::app::auth::current_user;
+use crate::app::events::empty_as_none;
+use crate::utils::db::Media;
+use crate::utils::slug::slugify;
+use crate::utils::types::{AppResult, AppRouter, AppState, SharedAppState};
+
+/// Largest request accepted when uploading files, across all files.
+const MAX_UPLOAD_BYTES: usize = 64 * 1024 * 1024;
+
+/// Add all `media` routes to the router.
+pub fn register_routes(router: AppRouter) -> AppRouter {
+    router
+        .route(
+            "/media",
+            get(library_page)
+                .post(upload_media_form)
+                .layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES)),
+        )
+        .route("/media/:media_id/alt", post(update_alt_form))
+        .route("/media/:media_id/delete", post(delete_media_form))
+        .route("/media/:media_id/:filename", get(media_file))
+}
+
+/// Display the media library, with a snippet to paste into posts for each file.
+async fn library_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Query(param): Query<SearchMedia>,
+) -> AppResult<Response> {
+    if current_user(&state, &cookies).await?.filter(|u| u.organizer).is_none() {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    render_library(&state, &param, &[]).await
+}
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+struct SearchMedia {
+    /// Only show files whose name or alt text contains this.
+    #[serde(default, deserialize_with = "empty_as_none")]
+    q: Option<String>,
+}
+
+/// Process the form to upload any number of files at once.
+///
+/// The alt text, if given, is used for all of them.
+async fn upload_media_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    mut multipart: Multipart,
+) -> AppResult<Response> {
+    let Some(uploader) = current_user(&state, &cookies).await?.filter(|u| u.organizer) else {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+
+    // The alt text could come after the files, so read everything before saving any.
+    let mut alt = String::new();
+    let mut files = vec![];
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("alt") => alt = field.text().await?.trim().to_string(),
+            Some("files") => {
+                let filename = field.file_name().unwrap_or("file").to_string();
+                let bytes = field.bytes().await?;
+                // Submitting the form without picking any files sends a single empty one.
+                if !bytes.is_empty() {
+                    files.push((filename, bytes.to_vec()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (filename, bytes) in files {
+        let file = tokio::task::spawn_blocking(move || identify_file(&filename, bytes)).await?;
+        save_media(&state, &file, &alt, uploader.id).await?;
+    }
+    Ok(Redirect::to("/media").into_response())
+}
+
+/// Change the alt text of a file.
+///
+/// Snippets already pasted into posts keep the old text.
+async fn update_alt_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(media_id): Path<i64>,
+    Form(form): Form<UpdateAlt>,
+) -> AppResult<Response> {
+    if current_user(&state, &cookies).await?.filter(|u| u.organizer).is_none() {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    state.db.update_media_alt(media_id, form.alt.trim()).await?;
+    Ok(Redirect::to("/media").into_response())
+}
+#[derive(serde::Deserialize)]
+struct UpdateAlt {
+    alt: String,
+}
+
+/// Remove a file for good, unless a post still links to it.
+async fn delete_media_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(media_id): Path<i64>,
+) -> AppResult<Response> {
+    if current_user(&state, &cookies).await?.filter(|u| u.organizer).is_none() {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(media) = state.db.lookup_media_by_id(media_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let posts = state.db.get_posts_linking_to(&format!("/media/{}/", media.id)).await?;
+    if !posts.is_empty() {
+        let titles: Vec<_> = posts.iter().map(|p| format!("\"{}\"", p.title)).collect();
+        let error = format!(
+            "{} can't be deleted, since it's used in {}. Remove it from those posts first.",
+            media.filename,
+            titles.join(", ")
+        );
+        return render_library(&state, &SearchMedia::default(), &[error]).await;
+    }
+
+    if let Some(media) = state.db.delete_media(media.id).await? {
+        let path = media_path(&state, &media);
+        if let Err(err) = tokio::fs::remove_file(&path).await {
+            tracing::warn!("media: removing {}: {err}", path.display());
+        }
+    }
+    Ok(Redirect::to("/media").into_response())
+}
+
+/// Serve a file from the library.
+///
+/// Only images are shown in the browser. Anything else is downloaded, so an
+/// uploaded HTML file can't run scripts on the site.
+async fn media_file(
+    State(state): State<SharedAppState>,
+    Path((media_id, _filename)): Path<(i64, String)>,
+) -> AppResult<Response> {
+    let Some(media) = state.db.lookup_media_by_id(media_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let disposition = match media.width {
+        Some(_) => "inline".to_string(),
+        None => format!("attachment; filename=\"{}\"", media.filename),
+    };
+    let headers = [
+        (header::CONTENT_TYPE, media.content_type.clone()),
+        (header::CONTENT_DISPOSITION, disposition),
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+    ];
+    let bytes = tokio::fs::read(media_path(&state, &media)).await?;
+    Ok((headers, bytes).into_response())
+}
+
+/// An uploaded file, ready to be saved.
+struct UploadedFile {
+    filename: String,
+    content_type: String,
+    /// Only for images, as they'd be displayed.
+    dimensions: Option<(u32, u32)>,
+    bytes: Vec<u8>,
+}
+
+/// Work out what kind of file an upload is, by its contents rather than its name.
+///
+/// Images the site can read get their dimensions measured. Anything else is kept as
+/// a generic download. This reads image headers, so run it on a blocking thread.
+fn identify_file(filename: &str, bytes: Vec<u8>) -> UploadedFile {
+    let (stem, extension) = filename.rsplit_once('.').unwrap_or((filename, ""));
+    let stem = match slugify(stem) {
+        stem if stem.is_empty() => "file".to_string(),
+        stem => stem,
+    };
+
+    if let Ok((format, dimensions)) = image_dimensions(&bytes) {
+        let extension = format.extensions_str().first().copied().unwrap_or("img");
+        return UploadedFile {
+            filename: format!("{stem}.{extension}"),
+            content_type: format.to_mime_type().to_string(),
+            dimensions: Some(dimensions),
+            bytes,
+        };
+    }
+
+    let (filename, content_type) = match slugify(extension) {
+        extension if extension.is_empty() => (stem, "application/octet-stream"),
+        extension if bytes.starts_with(b"%PDF-") => (format!("{stem}.{extension}"), "application/pdf"),
+        extension => (format!("{stem}.{extension}"), "application/octet-stream"),
+    };
+    UploadedFile { filename, content_type: content_type.to_string(), dimensions: None, bytes }
+}
+
+/// Measure an image, the right way up, without decoding the whole thing.
+fn image_dimensions(bytes: &[u8]) -> Result<(ImageFormat, (u32, u32))> {
+    let reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    let Some(format) = reader.format() else {
+        anyhow::bail!("not an image");
+    };
+    let mut decoder = reader.into_decoder()?;
+    let (width, height) = decoder.dimensions();
+    // Phones store photos sideways and say which way is up in the EXIF data, which browsers follow.
+    let dimensions = match decoder.orientation()? {
+        Orientation::Rotate90
+        | Orientation::Rotate270
+        | Orientation::Rotate90FlipH
+        | Orientation::Rotate270FlipH => (height, width),
+        _ => (width, height),
+    };
+    Ok((format, dimensions))
+}
+
+/// Record a file in the database, and write it to disk.
+async fn save_media(state: &AppState, file: &UploadedFile, alt: &str, uploader_id: i64) -> Result<()> {
+    let id = state
+        .db
+        .create_media(
+            &file.filename,
+            &file.content_type,
+            file.bytes.len(),
+            file.dimensions,
+            alt,
+            uploader_id,
+        )
+        .await?;
+    let Some(media) = state.db.lookup_media_by_id(id).await? else {
+        anyhow::bail!("media_id={id} disappeared after upload");
+    };
+
+    let written = async {
+        tokio::fs::create_dir_all(media_dir(state)).await?;
+        tokio::fs::write(media_path(state, &media), &file.bytes).await?;
+        anyhow::Ok(())
+    }
+    .await;
+    // Don't leave a broken file in the library.
+    if let Err(err) = written {
+        state.db.delete_media(media.id).await?;
+        return Err(err.context(format!("saving media_id={}", media.id)));
+    }
+    Ok(())
+}
+
+fn media_dir(state: &AppState) -> PathBuf {
+    state.config.app.uploads.join("media")
+}
+
+fn media_path(state: &AppState, media: &Media) -> PathBuf {
+    media_dir(state).join(media.id.to_string())
+}
+
+async fn render_library(state: &AppState, param: &SearchMedia, errors: &[String]) -> AppResult<Response> {
+    let mut ctx = tera::Context::new();
+    ctx.insert("media", &state.db.search_media(param.q.as_deref()).await?);
+    ctx.insert("params", param);
+    ctx.insert("errors", errors);
+
+    let html = state.templates.render("media.tera.html", &ctx).unwrap();
+    let status = match errors {
+        [] => StatusCode::OK,
+        _ => StatusCode::CONFLICT,
+    };
+    Ok((status, Html(html)).into_response())
+}
@@ -4,12 +4,23 @@ use std::sync::Arc;
 use tera::Tera;
 use tower_http::services::ServeDir;
 
-use crate::utils::{self, config::*, db::Db, email::Email};
+use crate::utils::{self, config::*, db::Db, email::Email, payments::PaymentProvider};
 
 mod auth;
+mod comments;
 mod events;
+mod feeds;
+mod galleries;
+mod guestlists;
 mod home;
+mod jobs;
+mod media;
+mod newsletter;
+mod payments;
 mod posts;
+mod promos;
+mod tickets;
+mod timetables;
 
 #[derive(Clone)]
 #[allow(unused)]
@@ -18,26 +29,48 @@ pub struct AppState {
     templates: Tera,
     db: Db,
     mail: Email,
+    /// `None` if no provider is configured, in which case only free tickets can be ordered.
+    payments: Option<Arc<dyn PaymentProvider>>,
 }
 
 pub async fn build(config: Config) -> Result<Router> {
-    let state = AppState {
+    let state = Arc::new(AppState {
         config: config.clone(),
         templates: utils::tera::templates()?,
-        db: Db::connect(&config.app.db).await?,
+        db: Db::connect(&config.app.db, config.app.timezone).await?,
         mail: Email::connect(config.email).await?,
-    };
+        payments: utils::payments::connect(config.payments, &config.app.url),
+    });
+
+    tokio::spawn(events::publish_scheduled_events(Arc::clone(&state)));
+    tokio::spawn(posts::publish_scheduled_posts(Arc::clone(&state)));
+    tokio::spawn(jobs::run(Arc::clone(&state)));
 
     let r = Router::new();
     let r = home::register_routes(r);
     let r = auth::register_routes(r);
     let r = posts::register_routes(r);
+    let r = newsletter::register_routes(r);
+    let r = comments::register_routes(r);
     let r = events::register_routes(r);
+    let r = guestlists::register_routes(r);
+    let r = timetables::register_routes(r);
+    let r = galleries::register_routes(r);
+    let r = media::register_routes(r);
+    let r = tickets::register_routes(r);
+    let r = promos::register_routes(r);
+    let r = payments::register_routes(r);
+    let r = feeds::register_routes(r);
 
     let r = r.nest_service("/assets", ServeDir::new("assets"));
     let r = utils::tracing::register(r);
 
-    let r = r.with_state(Arc::new(state));
+    let r = r.with_state(Arc::clone(&state));
+
+    // Event slugs are resolved before routing, so they need to wrap the whole router.
+    let r = Router::new()
+        .fallback_service(r)
+        .layer(axum::middleware::from_fn_with_state(state, events::resolve_event_urls));
 
     Ok(r)
 }
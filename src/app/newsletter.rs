@@ -0,0 +1,171 @@
+//! Emailing posts to newsletter subscribers.
+//!
+//! Users opt in to the newsletter themselves. Sending a post snapshots the
+//! subscriber list, and the emails go out in batches from `app::jobs`, with
+//! each recipient's status recorded so an interrupted send never emails
+//! anyone twice.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::get,
+    Form,
+};
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+
+use crate::app::auth::current_user;
+use crate::app::jobs;
+use crate::utils::db::{Newsletter, NewsletterRecipient, PostStatus};
+use crate::utils::types::{AppResult, AppRouter, SharedAppState};
+
+/// Add all `newsletter` routes to the router.
+pub fn register_routes(router: AppRouter) -> AppRouter {
+    router
+        .route("/newsletter", get(subscription_page).post(subscription_form))
+        .route("/newsletter/unsubscribe/:token", get(unsubscribe_page).post(unsubscribe_form))
+        .route("/p/:post/newsletter", get(post_newsletter_page).post(send_newsletter_form))
+}
+
+/// Display whether the logged-in user is subscribed, with a form to change it.
+async fn subscription_page(State(state): State<SharedAppState>, cookies: CookieJar) -> AppResult<Response> {
+    let Some(user) = current_user(&state, &cookies).await? else {
+        return Ok(Redirect::to("/").into_response());
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("subscribed", &state.db.is_newsletter_subscriber(user.id).await?);
+
+    let html = state.templates.render("newsletter.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
+}
+
+/// Process the form to subscribe to the newsletter, or unsubscribe.
+async fn subscription_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Form(form): Form<Subscription>,
+) -> AppResult<Response> {
+    let Some(user) = current_user(&state, &cookies).await? else {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+
+    match form.subscribe {
+        true => state.db.subscribe_to_newsletter(user.id).await?,
+        false => state.db.unsubscribe_from_newsletter(user.id).await?,
+    }
+    Ok(Redirect::to("/newsletter").into_response())
+}
+#[derive(serde::Deserialize)]
+struct Subscription {
+    subscribe: bool,
+}
+
+/// Ask to confirm unsubscribing, from the link in a newsletter.
+///
+/// Unsubscribing takes a POST, so link checkers which open every link in an email don't do it.
+async fn unsubscribe_page(
+    State(state): State<SharedAppState>,
+    Path(token): Path<String>,
+) -> AppResult<Response> {
+    let user = state.db.lookup_user_by_unsubscribe_token(&token).await?;
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("token", &token);
+    ctx.insert("email", &user.map(|u| u.email));
+    ctx.insert("done", &false);
+
+    let html = state.templates.render("newsletter-unsubscribe.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
+}
+
+/// Unsubscribe whoever an unsubscribe link belongs to.
+///
+/// This is also where mail clients send one-click unsubscribes from the `List-Unsubscribe` header.
+async fn unsubscribe_form(
+    State(state): State<SharedAppState>,
+    Path(token): Path<String>,
+) -> AppResult<Response> {
+    if let Some(user) = state.db.lookup_user_by_unsubscribe_token(&token).await? {
+        state.db.unsubscribe_from_newsletter(user.id).await?;
+    }
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("done", &true);
+
+    let html = state.templates.render("newsletter-unsubscribe.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
+}
+
+/// Display every time a post was sent as a newsletter, and who it went to.
+async fn post_newsletter_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(slug): Path<String>,
+) -> AppResult<Response> {
+    if current_user(&state, &cookies).await?.filter(|u| u.organizer).is_none() {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(post) = state.db.lookup_post_by_slug(&slug).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let mut sends = vec![];
+    for newsletter in state.db.get_post_newsletters(post.id).await? {
+        let recipients = state.db.get_newsletter_recipients(newsletter.id).await?;
+        sends.push(NewsletterSend { newsletter, recipients });
+    }
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("post", &post);
+    ctx.insert("sends", &sends);
+
+    let html = state.templates.render("post-newsletter.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
+}
+
+/// A newsletter, with the status of each recipient.
+#[derive(serde::Serialize)]
+struct NewsletterSend {
+    newsletter: Newsletter,
+    recipients: Vec<NewsletterRecipient>,
+}
+
+/// Start emailing a published post to subscribers, or resume an unfinished send.
+///
+/// Sending a post again once it's gone out has to be asked for, so a double
+/// click doesn't email everyone twice.
+async fn send_newsletter_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(slug): Path<String>,
+    Form(form): Form<SendNewsletter>,
+) -> AppResult<Response> {
+    let Some(sender) = current_user(&state, &cookies).await?.filter(|u| u.organizer) else {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+    let Some(post) = state.db.lookup_post_by_slug(&slug).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    if post.status != PostStatus::Published {
+        return Ok((StatusCode::BAD_REQUEST, "Only published posts can be sent.").into_response());
+    }
+
+    let now = Utc::now();
+    let newsletter_id = match state.db.get_post_newsletters(post.id).await?.first() {
+        Some(latest) if latest.finished_at.is_none() => latest.id,
+        Some(_) if !form.again => {
+            return Ok((StatusCode::BAD_REQUEST, "This post was already sent.").into_response());
+        }
+        _ => state.db.create_newsletter(post.id, Some(sender.id), now).await?,
+    };
+    jobs::schedule_newsletter(&state, newsletter_id, now).await?;
+    Ok(Redirect::to(&format!("/p/{}/newsletter", post.slug)).into_response())
+}
+#[derive(serde::Deserialize)]
+struct SendNewsletter {
+    /// Send a new copy even though the post already went out.
+    #[serde(default)]
+    again: bool,
+}
@@ -0,0 +1,118 @@
+//! Payment provider webhooks, and the checkout page for the fake provider.
+//!
+//! Orders are only marked paid, and their tickets issued, once a webhook
+//! with a good signature says so. The buyer coming back from the checkout
+//! page proves nothing.
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form,
+};
+use chrono::Utc;
+
+use crate::utils::payments::WebhookEvent;
+use crate::utils::types::{AppResult, AppRouter, SharedAppState};
+
+/// Add all `payments` routes to the router.
+pub fn register_routes(router: AppRouter) -> AppRouter {
+    router
+        .route("/payments/webhook", post(webhook))
+        .route("/payments/fake/:session_id", get(fake_checkout_page).post(fake_checkout_form))
+}
+
+/// Receive a webhook from the payment provider.
+async fn webhook(
+    State(state): State<SharedAppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AppResult<Response> {
+    handle_webhook(&state, &headers, &body).await
+}
+
+/// Act on a webhook, once its signature has been checked.
+///
+/// Providers can send the same webhook more than once, so this is idempotent.
+async fn handle_webhook(state: &SharedAppState, headers: &HeaderMap, body: &[u8]) -> AppResult<Response> {
+    let Some(payments) = &state.payments else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let event = match payments.verify_webhook(headers, body) {
+        Ok(event) => event,
+        Err(err) => {
+            tracing::warn!("rejected payment webhook: {err}");
+            return Ok(StatusCode::BAD_REQUEST.into_response());
+        }
+    };
+
+    match event {
+        WebhookEvent::Paid { session_id, payment_ref } => {
+            let Some(order) = state.db.lookup_order_by_payment_id(&session_id).await? else {
+                tracing::warn!("payment for unknown checkout={session_id}");
+                return Ok(StatusCode::OK.into_response());
+            };
+            if state.db.mark_order_paid(order.id, Some(&payment_ref), Utc::now()).await? {
+                tracing::info!("order={} paid", order.id);
+            }
+        }
+        WebhookEvent::Expired { session_id } => {
+            if let Some(order) = state.db.lookup_order_by_payment_id(&session_id).await? {
+                if state.db.cancel_order(order.id).await? {
+                    tracing::info!("order={} cancelled, its checkout expired", order.id);
+                }
+            }
+        }
+        WebhookEvent::Ignored => {}
+    }
+    Ok(StatusCode::OK.into_response())
+}
+
+/// Display the fake provider's checkout page.
+async fn fake_checkout_page(
+    State(state): State<SharedAppState>,
+    Path(session_id): Path<String>,
+) -> AppResult<Response> {
+    let Some(session) = state
+        .payments
+        .as_ref()
+        .and_then(|p| p.as_fake())
+        .and_then(|f| f.session(&session_id))
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("session", &session);
+
+    let html = state.templates.render("payment-fake.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
+}
+
+#[derive(serde::Deserialize)]
+struct FakeCheckoutForm {
+    paid: bool,
+}
+
+/// Pay for, or back out of, a fake checkout.
+///
+/// This goes through the same signed webhook as a real provider would send.
+async fn fake_checkout_form(
+    State(state): State<SharedAppState>,
+    Path(session_id): Path<String>,
+    Form(form): Form<FakeCheckoutForm>,
+) -> AppResult<Response> {
+    let Some(fake) = state.payments.as_ref().and_then(|p| p.as_fake()) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let Some(session) = fake.session(&session_id) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let (headers, body) = fake.webhook(&session.id, form.paid)?;
+    handle_webhook(&state, &headers, &body).await?;
+    let url = if form.paid { &session.success_url } else { &session.cancel_url };
+    Ok(Redirect::to(url).into_response())
+}
@@ -1,55 +1,602 @@
+use std::time::Duration;
+
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
     response::{Html, IntoResponse, Redirect, Response},
-    routing::get,
+    routing::{get, post},
     Form,
 };
+use axum_extra::extract::CookieJar;
+use chrono::{DateTime, Months, NaiveDate, Utc};
+use chrono_tz::Tz;
 
-use crate::utils::types::{AppResult, AppRouter, SharedAppState};
+use crate::app::auth::{current_user, is_organizer};
+use crate::app::comments::comment_threads;
+use crate::app::events::{
+    empty_as_none, empty_as_none_parsed, parse_datetime, revision_history, start_of_day,
+};
+use crate::app::jobs;
+use crate::utils::db::{
+    is_unique_violation, CommentTarget, Post, PostCursor, PostFields, PostSearch, PostStatus, RevisionKind,
+};
+use crate::utils::types::{AppResult, AppRouter, AppState, SharedAppState};
+use crate::utils::{markdown, slug};
 
 /// Add all `post` routes to the router.
 pub fn register_routes(router: AppRouter) -> AppRouter {
     router
+        .route("/posts", get(list_posts_page))
+        .route("/posts/:year", get(year_posts_page))
+        .route("/posts/:year/:month", get(month_posts_page))
+        .route("/u/:user_id", get(author_page))
         .route("/p/new", get(create_post_page).post(create_post_form))
+        .route("/p/preview", post(preview_post_form))
         .route("/p/:post", get(view_post_page))
+        .route("/p/:post/edit", get(update_post_page).post(update_post_form))
+        .route("/p/:post/status", post(update_post_status_form))
+        .route("/p/:post/delete", post(delete_post_form))
+        .route("/p/:post/history", get(post_history_page))
+        .route("/p/:post/history/:revision_id/restore", post(restore_post_revision_form))
+}
+
+/// Path segments under `/p/` used by other routes, which posts can't take as their slug.
+const RESERVED_SLUGS: [&str; 2] = ["new", "preview"];
+
+/// Number of posts shown per page of [`list_posts_page`].
+const POSTS_PER_PAGE: u32 = 10;
+
+/// Longest excerpt of a post shown in listings, in characters.
+const EXCERPT_CHARS: usize = 280;
+
+/// Display published posts, newest first.
+async fn list_posts_page(
+    State(state): State<SharedAppState>,
+    Query(param): Query<ListPosts>,
+) -> AppResult<Response> {
+    render_post_list(&state, param, "/posts".into(), None, None).await
+}
+
+/// Display the posts published in a year.
+async fn year_posts_page(
+    State(state): State<SharedAppState>,
+    Path(year): Path<i32>,
+    Query(param): Query<ListPosts>,
+) -> AppResult<Response> {
+    let Some(start) = NaiveDate::from_ymd_opt(year, 1, 1) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let archive = Archive { start, end: start + Months::new(12) };
+    let title = format!("Posts from {year}");
+    render_post_list(&state, param, format!("/posts/{year}"), Some(title), Some(archive)).await
+}
+
+/// Display the posts published in a month.
+async fn month_posts_page(
+    State(state): State<SharedAppState>,
+    Path((year, month)): Path<(i32, u32)>,
+    Query(param): Query<ListPosts>,
+) -> AppResult<Response> {
+    let Some(start) = NaiveDate::from_ymd_opt(year, month, 1) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let archive = Archive { start, end: start + Months::new(1) };
+    let title = start.format("Posts from %B %Y").to_string();
+    render_post_list(&state, param, format!("/posts/{year}/{month}"), Some(title), Some(archive)).await
+}
+
+/// Display an author's profile, with the posts they've written.
+///
+/// Only people with published posts have a profile, so this doesn't reveal who else has an account.
+async fn author_page(
+    State(state): State<SharedAppState>,
+    Path(user_id): Path<i64>,
+    Query(mut param): Query<ListPosts>,
+) -> AppResult<Response> {
+    let Some(author) = state.db.lookup_post_author(user_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    param.author = Some(author.user_id);
+    let title = format!("Posts by {}", author.name);
+    render_post_list(&state, param, format!("/u/{user_id}"), Some(title), None).await
+}
+
+/// A range of dates to list posts from.
+struct Archive {
+    start: NaiveDate,
+    /// First day after the range.
+    end: NaiveDate,
+}
+
+async fn render_post_list(
+    state: &AppState,
+    param: ListPosts,
+    path: String,
+    title: Option<String>,
+    archive: Option<Archive>,
+) -> AppResult<Response> {
+    let cursor = match param.cursor.as_deref().map(PostCursor::parse) {
+        None => None,
+        Some(Some(cursor)) => Some(cursor),
+        Some(None) => return Ok((StatusCode::BAD_REQUEST, "Invalid cursor.").into_response()),
+    };
+
+    // Archives are whole months in the site's default timezone.
+    let tz = state.config.app.timezone;
+    let search = PostSearch {
+        author_id: param.author,
+        published_after: archive.as_ref().map(|a| start_of_day(a.start, tz)),
+        published_before: archive.as_ref().map(|a| start_of_day(a.end, tz)),
+        cursor,
+        limit: POSTS_PER_PAGE,
+    };
+    let page = state.db.search_posts(&search).await?;
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("posts", &with_excerpts(&page.posts));
+    ctx.insert("next_cursor", &page.next.map(|c| c.to_string()));
+    ctx.insert("params", &param);
+    ctx.insert("path", &path);
+    ctx.insert("title", &title);
+    ctx.insert("archive", &state.db.get_post_archive(tz).await?);
+    ctx.insert("authors", &state.db.get_post_authors().await?);
+
+    let html = state.templates.render("posts.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
+}
+
+/// Query parameters for [`list_posts_page`] and the archive pages.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct ListPosts {
+    /// ID of the user to only show posts by.
+    #[serde(default, deserialize_with = "empty_as_none_parsed")]
+    author: Option<i64>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    cursor: Option<String>,
+}
+
+/// A post in a listing, with the start of its body as plain text.
+#[derive(serde::Serialize)]
+pub(crate) struct PostSummary<'a> {
+    post: &'a Post,
+    excerpt: String,
+}
+
+pub(crate) fn with_excerpts(posts: &[Post]) -> Vec<PostSummary<'_>> {
+    let summarize = |post| PostSummary { post, excerpt: markdown::excerpt(&post.body, EXCERPT_CHARS) };
+    posts.iter().map(summarize).collect()
 }
 
 /// Display a single post.
+///
+/// Drafts are only shown to organizers, and old slugs permanently redirect
+/// to the current one, so links shared before a rename keep working.
 async fn view_post_page(
     State(state): State<SharedAppState>,
-    Path(post): Path<String>,
+    cookies: CookieJar,
+    Path(slug): Path<String>,
+    Query(param): Query<ViewPost>,
 ) -> AppResult<Response> {
-    let Some(post) = state.db.lookup_post_by_slug(&post).await? else {
-        return Ok(StatusCode::NOT_FOUND.into_response());
+    let user = current_user(&state, &cookies).await?;
+    let organizer = user.as_ref().is_some_and(|u| u.organizer);
+    let Some(post) = state.db.lookup_post_by_slug(&slug).await? else {
+        return match state.db.lookup_post_by_old_slug(&slug).await? {
+            Some(post) if post.status == PostStatus::Published || organizer => {
+                let location = format!("/p/{}", post.slug);
+                Ok((StatusCode::MOVED_PERMANENTLY, [(header::LOCATION, location)]).into_response())
+            }
+            Some(post) if param.can_preview(&post) => {
+                let location = format!("/p/{}?preview={}", post.slug, param.preview.unwrap_or_default());
+                Ok(Redirect::to(&location).into_response())
+            }
+            _ => Ok(StatusCode::NOT_FOUND.into_response()),
+        };
     };
+    let preview = post.status != PostStatus::Published;
+    if preview && !organizer && !param.can_preview(&post) {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
 
     let mut ctx = tera::Context::new();
+    ctx.insert("body_html", &render_body(&state, &post).await?);
     ctx.insert("post", &post);
+    ctx.insert("organizer", &organizer);
+    ctx.insert("preview", &preview);
+    ctx.insert("timezone", state.config.app.timezone.name());
+    let comments = comment_threads(&state, CommentTarget::Post, post.id, user.as_ref()).await?;
+    ctx.insert("comments", &comments);
+    ctx.insert("comments_url", &format!("/p/{}/comments", post.slug));
+    ctx.insert("comments_open", &!preview);
+    ctx.insert("user", &user);
 
     let html = state.templates.render("post.tera.html", &ctx).unwrap();
     Ok(Html(html).into_response())
 }
 
+#[derive(serde::Deserialize)]
+struct ViewPost {
+    /// Secret from a post's preview link, which shows it before it's published.
+    #[serde(default, deserialize_with = "empty_as_none")]
+    preview: Option<String>,
+}
+
+impl ViewPost {
+    fn can_preview(&self, post: &Post) -> bool {
+        self.preview.is_some() && self.preview == post.preview_token
+    }
+}
+
+/// Render a post's Markdown body to HTML, caching it against the post's latest revision.
+pub(crate) async fn render_body(state: &AppState, post: &Post) -> anyhow::Result<String> {
+    if let Some(html) = &post.body_html {
+        return Ok(html.clone());
+    }
+    let html = markdown::render(&post.body);
+    if let Some(revision_id) = post.revision_id {
+        state.db.set_revision_html(revision_id, &html).await?;
+    }
+    Ok(html)
+}
+
+#[derive(serde::Deserialize)]
+struct PreviewForm {
+    body: String,
+}
+
+/// Render a post body for the live preview while it's being written.
+async fn preview_post_form(Form(form): Form<PreviewForm>) -> Html<String> {
+    Html(markdown::render(&form.body))
+}
+
 /// Display the form to create a new post.
-async fn create_post_page(State(state): State<SharedAppState>) -> AppResult<Response> {
-    let ctx = tera::Context::new();
+async fn create_post_page(State(state): State<SharedAppState>, cookies: CookieJar) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    render_create_form(&state, None, None).await
+}
+
+/// Render the form to create a post, with what was entered and why it couldn't be saved, if
+/// that's why it's being shown again.
+async fn render_create_form(
+    state: &AppState,
+    fields: Option<&PostFields>,
+    error: Option<&str>,
+) -> AppResult<Response> {
+    let mut ctx = tera::Context::new();
+    ctx.insert("fields", &fields);
+    ctx.insert("error", &error);
+    ctx.insert("organizers", &state.db.get_organizers().await?);
+
     let html = state.templates.render("post-create.tera.html", &ctx).unwrap();
-    Ok(Html(html).into_response())
+    let status = match error {
+        Some(_) => StatusCode::BAD_REQUEST,
+        None => StatusCode::OK,
+    };
+    Ok((status, Html(html)).into_response())
 }
 
-/// Process the form and create a new post.
+/// Process the form and create a new post, written by whoever is logged in.
+///
+/// New posts start out as drafts, to be published from the edit page.
 async fn create_post_form(
     State(state): State<SharedAppState>,
-    Form(form): Form<CreatePost>,
-) -> AppResult<impl IntoResponse> {
-    let _event_id = state.db.create_post(&form.title, &form.slug, &form.author, &form.body).await?;
-    Ok(Redirect::to(&format!("{}/p/{}", state.config.app.url, form.slug)))
+    cookies: CookieJar,
+    Form(form): Form<Vec<(String, String)>>,
+) -> AppResult<Response> {
+    let Some(editor) = current_user(&state, &cookies).await?.filter(|u| u.organizer) else {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+    let (fields, authors) = match parse_post_form(&form) {
+        Ok(form) => form,
+        Err((fields, err)) => return render_create_form(&state, Some(&fields), Some(&err)).await,
+    };
+    if state.db.is_post_slug_taken(&fields.slug, None).await? {
+        return render_create_form(&state, Some(&fields), Some(SLUG_TAKEN)).await;
+    }
+
+    let author_ids = authors.with_lead(editor.id);
+    let id = match state.db.create_post(&fields, &author_ids, Some(editor.id)).await {
+        // Someone else took the URL since it was checked.
+        Err(err) if is_unique_violation(&err) => {
+            return render_create_form(&state, Some(&fields), Some(SLUG_TAKEN)).await;
+        }
+        result => result?,
+    };
+    edit_redirect(&state, id).await
+}
+
+const SLUG_TAKEN: &str = "That URL is already taken by another post.";
+
+/// Go to the edit page of a post which was just saved, at its possibly generated slug.
+async fn edit_redirect(state: &AppState, id: i64) -> AppResult<Response> {
+    let Some(post) = state.db.lookup_post_by_id(id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    Ok(Redirect::to(&format!("/p/{}/edit", post.slug)).into_response())
+}
+
+/// Who's credited on a post, as submitted in its form.
+struct PostAuthorIds {
+    /// Only on the edit form, since new posts are written by whoever creates them.
+    lead: Option<i64>,
+    coauthors: Vec<i64>,
+}
+
+impl PostAuthorIds {
+    /// All author IDs, in order, led by `lead`.
+    fn with_lead(&self, lead: i64) -> Vec<i64> {
+        let coauthors = self.coauthors.iter().copied().filter(|&id| id != lead);
+        std::iter::once(lead).chain(coauthors).collect()
+    }
+}
+
+/// Parse the post form's fields.
+///
+/// On error, returns what was entered, to show the form again, along with a user-facing
+/// message. The form is read as raw pairs, since a post can have several co-authors.
+fn parse_post_form(form: &[(String, String)]) -> Result<(PostFields, PostAuthorIds), (PostFields, String)> {
+    let field = |name: &str| form.iter().find(|(key, _)| key == name).map_or("", |(_, value)| value.as_str());
+
+    // Whatever's typed in is cleaned up into a usable URL, and left blank to generate one.
+    let fields = PostFields {
+        title: field("title").trim().to_string(),
+        slug: slug::slugify(field("slug")),
+        body: field("body").to_string(),
+    };
+    if !fields.slug.is_empty() {
+        if let Err(err) = slug::validate(&fields.slug, &RESERVED_SLUGS) {
+            return Err((fields, err));
+        }
+    }
+
+    let lead = match field("author_id") {
+        "" => None,
+        id => match id.parse() {
+            Ok(id) => Some(id),
+            Err(_) => return Err((fields, "Invalid author.".into())),
+        },
+    };
+    let mut coauthors = vec![];
+    for (_, value) in form.iter().filter(|(key, _)| key == "coauthor_id") {
+        match value.parse() {
+            Ok(id) => coauthors.push(id),
+            Err(_) => return Err((fields, "Invalid co-author.".into())),
+        }
+    }
+
+    Ok((fields, PostAuthorIds { lead, coauthors }))
+}
+
+/// Display the form to edit a post.
+async fn update_post_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(slug): Path<String>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(post) = state.db.lookup_post_by_slug(&slug).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    render_edit_form(&state, &post, None, None).await
 }
+
+/// Render the form to edit a post, with what was entered and why it couldn't be saved, if
+/// that's why it's being shown again.
+async fn render_edit_form(
+    state: &AppState,
+    post: &Post,
+    fields: Option<&PostFields>,
+    error: Option<&str>,
+) -> AppResult<Response> {
+    let fields = fields.cloned().unwrap_or_else(|| PostFields {
+        title: post.title.clone(),
+        slug: post.slug.clone(),
+        body: post.body.clone(),
+    });
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("post", post);
+    ctx.insert("fields", &fields);
+    ctx.insert("error", &error);
+    ctx.insert("organizers", &state.db.get_organizers().await?);
+    ctx.insert("url", &state.config.app.url);
+    ctx.insert("timezone", state.config.app.timezone.name());
+
+    let html = state.templates.render("post-edit.tera.html", &ctx).unwrap();
+    let status = match error {
+        Some(_) => StatusCode::BAD_REQUEST,
+        None => StatusCode::OK,
+    };
+    Ok((status, Html(html)).into_response())
+}
+
+/// Process the form to edit a post, saving a new revision.
+///
+/// Posts from before authors were users can be left without one, keeping their old byline.
+async fn update_post_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(slug): Path<String>,
+    Form(form): Form<Vec<(String, String)>>,
+) -> AppResult<Response> {
+    let Some(editor) = current_user(&state, &cookies).await?.filter(|u| u.organizer) else {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+    let Some(post) = state.db.lookup_post_by_slug(&slug).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let (fields, authors) = match parse_post_form(&form) {
+        Ok(form) => form,
+        Err((fields, err)) => return render_edit_form(&state, &post, Some(&fields), Some(&err)).await,
+    };
+    if state.db.is_post_slug_taken(&fields.slug, Some(post.id)).await? {
+        return render_edit_form(&state, &post, Some(&fields), Some(SLUG_TAKEN)).await;
+    }
+
+    let author_ids = authors.lead.map(|lead| authors.with_lead(lead)).unwrap_or_default();
+    match state.db.update_post(post.id, &fields, Some(&author_ids), Some(editor.id)).await {
+        Err(err) if is_unique_violation(&err) => {
+            return render_edit_form(&state, &post, Some(&fields), Some(SLUG_TAKEN)).await;
+        }
+        result => result?,
+    }
+    edit_redirect(&state, post.id).await
+}
+
 #[derive(serde::Deserialize)]
-struct CreatePost {
-    title: String,
-    slug: String,
-    author: String,
-    body: String,
+struct UpdatePostStatus {
+    status: PostStatus,
+    /// Time in the site's timezone, from a `datetime-local` input.
+    #[serde(default)]
+    publish_at: String,
+    /// Email the post to newsletter subscribers once it's published on schedule.
+    #[serde(default)]
+    newsletter: bool,
+}
+
+impl UpdatePostStatus {
+    /// When to publish the post, required when scheduling it.
+    fn publish_at(&self, tz: Tz) -> Result<Option<DateTime<Utc>>, String> {
+        if self.status != PostStatus::Scheduled {
+            return Ok(None);
+        }
+        let publish_at = parse_datetime("Publish", &self.publish_at, tz)?;
+        if publish_at <= Utc::now() {
+            return Err("Scheduled publish time must be in the future.".into());
+        }
+        Ok(Some(publish_at))
+    }
+}
+
+/// Publish, schedule, or unpublish a post.
+async fn update_post_status_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(slug): Path<String>,
+    Form(form): Form<UpdatePostStatus>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(post) = state.db.lookup_post_by_slug(&slug).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let publish_at = match form.publish_at(state.config.app.timezone) {
+        Ok(publish_at) => publish_at,
+        Err(err) => return Ok((StatusCode::BAD_REQUEST, err).into_response()),
+    };
+
+    let newsletter = form.newsletter && publish_at.is_some();
+    state
+        .db
+        .update_post_status(post.id, form.status, publish_at, newsletter, Utc::now())
+        .await?;
+    if form.status == PostStatus::Published && post.status != PostStatus::Published {
+        announce_post(&state, post.id).await?;
+    }
+    Ok(Redirect::to(&format!("/p/{}/edit", post.slug)).into_response())
+}
+
+/// Periodically publish scheduled posts once their publish time has passed.
+pub async fn publish_scheduled_posts(state: SharedAppState) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        let post_ids = match state.db.publish_scheduled_posts(Utc::now()).await {
+            Ok(post_ids) => post_ids,
+            Err(err) => {
+                tracing::error!("publishing scheduled posts: {err:#}");
+                continue;
+            }
+        };
+        for post_id in post_ids {
+            tracing::info!("published scheduled post_id={post_id}");
+            if let Err(err) = announce_post(&state, post_id).await {
+                tracing::error!("announcing post_id={post_id}: {err:#}");
+            }
+        }
+    }
+}
+
+/// Let the world know a post was just published, through webhooks, and the
+/// newsletter if it was scheduled to be emailed.
+async fn announce_post(state: &AppState, post_id: i64) -> anyhow::Result<()> {
+    let Some(post) = state.db.lookup_post_by_id(post_id).await? else {
+        return Ok(());
+    };
+    if post.newsletter_on_publish {
+        let now = Utc::now();
+        let newsletter_id = state.db.create_newsletter(post.id, None, now).await?;
+        jobs::schedule_newsletter(state, newsletter_id, now).await?;
+    }
+    jobs::notify_post_published(state, &post).await
+}
+
+/// Delete a post, along with its history.
+async fn delete_post_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(slug): Path<String>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(post) = state.db.lookup_post_by_slug(&slug).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    state.db.delete_post(post.id).await?;
+    Ok(Redirect::to("/").into_response())
+}
+
+/// Display every revision of a post, with what changed in each one.
+async fn post_history_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(slug): Path<String>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(post) = state.db.lookup_post_by_slug(&slug).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let revisions = state.db.get_revisions(RevisionKind::Post, post.id).await?;
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("post", &post);
+    ctx.insert("history", &revision_history(&revisions)?);
+
+    let html = state.templates.render("post-history.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
+}
+
+/// Put a post back the way it was in an earlier revision.
+///
+/// This is saved as a new revision, so a restore can itself be undone.
+async fn restore_post_revision_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path((slug, revision_id)): Path<(String, i64)>,
+) -> AppResult<Response> {
+    let Some(editor) = current_user(&state, &cookies).await?.filter(|u| u.organizer) else {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+    let Some(post) = state.db.lookup_post_by_slug(&slug).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let Some(revision) = state.db.lookup_revision(RevisionKind::Post, post.id, revision_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let mut fields: PostFields = serde_json::from_str(&revision.snapshot)?;
+    // Another post may have taken the old URL since, in which case keep the current one.
+    if fields.slug.is_empty() || state.db.is_post_slug_taken(&fields.slug, Some(post.id)).await? {
+        fields.slug = post.slug.clone();
+    }
+    state.db.update_post(post.id, &fields, None, Some(editor.id)).await?;
+    Ok(Redirect::to(&format!("/p/{}/history", fields.slug)).into_response())
 }
@@ -0,0 +1,225 @@
+//! Promo codes for ticket discounts, and reports on how they've been used.
+//!
+//! Codes are checked and applied at checkout, in [`crate::app::tickets`].
+
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form,
+};
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+use chrono_tz::Tz;
+
+use crate::app::{auth::is_organizer, events::parse_datetime, tickets::parse_money};
+use crate::utils::db::{DiscountKind, Event, PromoCode, PromoCodeFields};
+use crate::utils::types::{AppResult, AppRouter, AppState, SharedAppState};
+
+/// Add all `promos` routes to the router.
+pub fn register_routes(router: AppRouter) -> AppRouter {
+    router
+        .route("/e/:event_id/promos", get(promo_codes_page).post(create_promo_code_form))
+        .route("/e/:event_id/promos/:promo_code_id", get(promo_code_page))
+        .route("/e/:event_id/promos/:promo_code_id/expire", post(expire_promo_code_form))
+        .route("/e/:event_id/promos/:promo_code_id/delete", post(delete_promo_code_form))
+}
+
+/// Display an event's promo codes, with sales totals for each code and promoter.
+async fn promo_codes_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let codes = state.db.get_promo_codes(event.id).await?;
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("event", &event);
+    ctx.insert("promoters", &promoter_totals(&codes));
+    ctx.insert("codes", &codes);
+    ctx.insert("tiers", &state.db.get_ticket_tiers(event.id).await?);
+    ctx.insert("url", &state.config.app.url);
+
+    let html = state.templates.render("promos.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
+}
+
+/// Sales through a promoter's codes, for attributing them.
+#[derive(Default, serde::Serialize)]
+struct PromoterTotals {
+    promoter: String,
+    uses: i64,
+    tickets: i64,
+    discounted: i64,
+    revenue: i64,
+}
+
+/// Add up the usage of each promoter's codes, skipping codes that weren't given to anyone.
+fn promoter_totals(codes: &[PromoCode]) -> Vec<PromoterTotals> {
+    let mut promoters: BTreeMap<&str, PromoterTotals> = BTreeMap::new();
+    for code in codes.iter().filter(|c| !c.promoter.is_empty()) {
+        let totals = promoters.entry(&code.promoter).or_default();
+        totals.promoter.clone_from(&code.promoter);
+        totals.uses += code.uses;
+        totals.tickets += code.tickets;
+        totals.discounted += code.discounted;
+        totals.revenue += code.revenue;
+    }
+    promoters.into_values().collect()
+}
+
+/// Display every order which used a promo code.
+async fn promo_code_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path((event_id, promo_code_id)): Path<(i64, i64)>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let Some(code) = state.db.lookup_promo_code_by_id(event.id, promo_code_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("event", &event);
+    ctx.insert("orders", &state.db.get_promo_code_orders(code.id).await?);
+    ctx.insert("code", &code);
+
+    let html = state.templates.render("promo.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
+}
+
+/// Process the form to add a promo code to an event.
+///
+/// The form is read as raw pairs, since a code can be limited to several tiers.
+async fn create_promo_code_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+    Form(form): Form<Vec<(String, String)>>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let fields = match parse_promo_code_form(&form, &event, &state) {
+        Ok(fields) => fields,
+        Err(err) => return Ok((StatusCode::BAD_REQUEST, err).into_response()),
+    };
+    if state.db.is_promo_code_taken(event.id, &fields.code).await? {
+        let msg = format!("This event already has a promo code {}.", fields.code);
+        return Ok((StatusCode::BAD_REQUEST, msg).into_response());
+    }
+
+    state.db.create_promo_code(event.id, &fields).await?;
+    Ok(Redirect::to(&format!("/e/{event_id}/promos")).into_response())
+}
+
+/// Parse and validate the submitted fields, returning a user-facing message on error.
+///
+/// Expiry is a wall-clock time in the event's timezone, like the event form.
+fn parse_promo_code_form(
+    form: &[(String, String)],
+    event: &Event,
+    state: &AppState,
+) -> Result<PromoCodeFields, String> {
+    let field = |name: &str| form.iter().find(|(key, _)| key == name).map_or("", |(_, value)| value.trim());
+
+    let code = field("code").to_uppercase();
+    if !(3..=32).contains(&code.len()) {
+        return Err("Promo codes must be between 3 and 32 characters.".into());
+    }
+    if !code.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') {
+        return Err("Promo codes can only contain letters, numbers, hyphens, and underscores.".into());
+    }
+
+    let kind = match field("kind") {
+        "percent" => DiscountKind::Percent,
+        "fixed" => DiscountKind::Fixed,
+        _ => return Err("Pick a kind of discount.".into()),
+    };
+    let amount = match kind {
+        DiscountKind::Percent => match field("amount").trim_end_matches('%').parse::<i64>() {
+            Ok(amount) if (1..=100).contains(&amount) => amount,
+            _ => return Err("A percentage discount must be a whole number from 1 to 100.".into()),
+        },
+        DiscountKind::Fixed => match parse_money("Discount", field("amount"))? {
+            0 => return Err("Discount must be more than zero.".into()),
+            amount => amount,
+        },
+    };
+
+    let max_uses = match field("max_uses") {
+        "" => None,
+        max_uses => match max_uses.parse::<u32>() {
+            Ok(max_uses) if max_uses > 0 => Some(max_uses.into()),
+            _ => return Err("Usage limit must be a whole number above zero.".into()),
+        },
+    };
+    let tz: Tz = event.timezone.parse().unwrap_or(state.config.app.timezone);
+    let expires_at = match field("expires_at") {
+        "" => None,
+        expires_at => Some(parse_datetime("Expiry", expires_at, tz)?),
+    };
+
+    let mut tier_ids = vec![];
+    for (_, value) in form.iter().filter(|(key, _)| key == "tier_id") {
+        tier_ids.push(value.parse().map_err(|_| "Invalid ticket tier.".to_string())?);
+    }
+
+    Ok(PromoCodeFields {
+        code,
+        kind,
+        amount,
+        max_uses,
+        expires_at,
+        promoter: field("promoter").to_string(),
+        tier_ids,
+    })
+}
+
+/// Stop a promo code from being used any more, keeping its sales on record.
+async fn expire_promo_code_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path((event_id, promo_code_id)): Path<(i64, i64)>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    state.db.expire_promo_code(event_id, promo_code_id, Utc::now()).await?;
+    Ok(Redirect::to(&format!("/e/{event_id}/promos")).into_response())
+}
+
+/// Remove a promo code, if it hasn't been used yet.
+async fn delete_promo_code_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path((event_id, promo_code_id)): Path<(i64, i64)>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    if !state.db.delete_promo_code(event_id, promo_code_id).await? {
+        let msg = "This promo code has been used, so it can't be removed. Expire it instead.";
+        return Ok((StatusCode::BAD_REQUEST, msg).into_response());
+    }
+    Ok(Redirect::to(&format!("/e/{event_id}/promos")).into_response())
+}
@@ -0,0 +1,435 @@
+//! Ticket sales: tiers, checkout, and orders.
+//!
+//! Promo codes are managed in [`crate::app::promos`], but they're validated
+//! here at checkout, since the browser can't be trusted with the price.
+//!
+//! Paid orders are held while the buyer pays with the payment provider, and
+//! their tickets are only issued once the provider's webhook confirms it, in
+//! [`crate::app::payments`].
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form,
+};
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+
+use crate::app::auth::{current_user, is_organizer};
+use crate::utils::db::{EventStatus, NewOrder, OrderStatus};
+use crate::utils::payments::Checkout as PaymentCheckout;
+use crate::utils::types::{AppResult, AppRouter, AppState, SharedAppState};
+
+/// Most tickets one order can be for.
+const MAX_TICKETS_PER_ORDER: i64 = 10;
+
+/// Add all `tickets` routes to the router.
+pub fn register_routes(router: AppRouter) -> AppRouter {
+    router
+        .route("/e/:event_id/tickets", get(tickets_page).post(checkout_form))
+        .route("/e/:event_id/tickets/tiers", post(create_tier_form))
+        .route("/e/:event_id/tickets/tiers/:tier_id/delete", post(delete_tier_form))
+        .route("/e/:event_id/orders", get(orders_page))
+        .route("/orders/:order_id", get(order_page))
+        .route("/orders/:order_id/refund", post(refund_order_form))
+}
+
+/// Display an event's tickets, with a form to buy them.
+///
+/// A promo code can be filled in with `?code=`, so promoters can share links with theirs.
+async fn tickets_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+    Query(param): Query<TicketsPage>,
+) -> AppResult<Response> {
+    render_tickets(&state, &cookies, event_id, param.code.as_deref().unwrap_or_default(), None).await
+}
+#[derive(serde::Deserialize)]
+struct TicketsPage {
+    code: Option<String>,
+}
+
+/// Process the checkout form, place an order, and send the buyer off to pay for it.
+///
+/// Free orders, e.g. with a 100% promo code, are paid as soon as they're placed.
+async fn checkout_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+    Form(form): Form<Checkout>,
+) -> AppResult<Response> {
+    let Some(user) = current_user(&state, &cookies).await? else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let code = form.code.trim();
+    let error = |err: String| render_tickets(&state, &cookies, event.id, code, Some(err));
+
+    if event.status != EventStatus::Published {
+        return Ok((StatusCode::BAD_REQUEST, "This event isn't selling tickets.").into_response());
+    }
+    if !(1..=MAX_TICKETS_PER_ORDER).contains(&form.quantity) {
+        return error(format!("You can buy between 1 and {MAX_TICKETS_PER_ORDER} tickets at a time.")).await;
+    }
+    let tiers = state.db.get_ticket_tiers(event.id).await?;
+    let Some(tier) = tiers.iter().find(|t| t.id == form.tier_id) else {
+        return error("Please pick which tickets you want.".into()).await;
+    };
+    match tier.remaining() {
+        Some(0) => return error(format!("{} tickets are sold out.", tier.name)).await,
+        Some(remaining) if remaining < form.quantity => {
+            return error(format!("There are only {remaining} {} tickets left.", tier.name)).await;
+        }
+        _ => {}
+    }
+
+    let promo_code = match code {
+        "" => None,
+        code => match state.db.lookup_promo_code_by_code(event.id, code).await? {
+            Some(promo_code) => Some(promo_code),
+            None => return error(format!("The promo code {} doesn't exist.", code.to_uppercase())).await,
+        },
+    };
+    if let Some(promo_code) = &promo_code {
+        if let Err(err) = promo_code.check(tier.id, Utc::now()) {
+            return error(err).await;
+        }
+    }
+
+    let subtotal = tier.price * form.quantity;
+    let discount = promo_code.as_ref().map_or(0, |p| p.discount(subtotal));
+    let total = subtotal - discount;
+    if total > 0 && state.payments.is_none() {
+        return error("Sorry, we can't take payments online right now.".into()).await;
+    }
+    let order = NewOrder {
+        event_id: event.id,
+        tier_id: tier.id,
+        user_id: user.id,
+        quantity: form.quantity,
+        subtotal,
+        discount,
+        total,
+        promo_code_id: promo_code.as_ref().map(|p| p.id),
+    };
+    // Someone else may have taken the last tickets or code uses since the checks above.
+    let Some(order_id) = state.db.create_order(&order).await? else {
+        return error("Those tickets or that promo code just ran out, sorry!".into()).await;
+    };
+
+    let Some(payments) = state.payments.as_ref().filter(|_| total > 0) else {
+        state.db.mark_order_paid(order_id, None, Utc::now()).await?;
+        return Ok(Redirect::to(&format!("/orders/{order_id}")).into_response());
+    };
+    let url = &state.config.app.url;
+    let description = format!("{} x {}: {}", form.quantity, tier.name, event.title);
+    let success_url = format!("{url}/orders/{order_id}");
+    // Backing out leaves the order pending until the provider expires the checkout, and the tickets are released.
+    let cancel_url = format!("{url}/e/{}/tickets", event.slug);
+    let checkout = PaymentCheckout {
+        order_id,
+        description: &description,
+        amount: total,
+        success_url: &success_url,
+        cancel_url: &cancel_url,
+    };
+    let session = match payments.create_checkout(&checkout).await {
+        Ok(session) => session,
+        Err(err) => {
+            tracing::error!("starting checkout for order={order_id}: {err}");
+            state.db.cancel_order(order_id).await?;
+            return error("Sorry, we couldn't start the payment, please try again.".into()).await;
+        }
+    };
+    state.db.set_order_payment_id(order_id, &session.id).await?;
+    Ok(Redirect::to(&session.url).into_response())
+}
+#[derive(serde::Deserialize)]
+struct Checkout {
+    tier_id: i64,
+    quantity: i64,
+    #[serde(default)]
+    code: String,
+}
+
+/// Display an order to the buyer.
+async fn order_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(order_id): Path<i64>,
+) -> AppResult<Response> {
+    let Some(user) = current_user(&state, &cookies).await? else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    let Some(order) = state.db.lookup_order_by_id(order_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    // Orders are private to whoever placed them.
+    if order.user_id != user.id && !user.organizer {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    let Some(event) = state.db.lookup_event_by_event_id(&order.event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("event", &event);
+    ctx.insert("tickets", &state.db.get_order_tickets(order.id).await?);
+    ctx.insert("order", &order);
+
+    let html = state.templates.render("order.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
+}
+
+/// Display all of an event's orders, for organizers.
+async fn orders_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("event", &event);
+    ctx.insert("orders", &state.db.get_event_orders(event.id).await?);
+
+    let html = state.templates.render("orders.tera.html", &ctx).unwrap();
+    Ok(Html(html).into_response())
+}
+
+/// Refund a paid order in full, through the payment provider it was paid with.
+async fn refund_order_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(order_id): Path<i64>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(order) = state.db.lookup_order_by_id(order_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    if order.status != OrderStatus::Paid {
+        return Ok((StatusCode::BAD_REQUEST, "Only paid orders can be refunded.").into_response());
+    }
+    if order.payment_ref.is_some() && state.payments.is_none() {
+        let msg = "No payment provider is set up to refund through.";
+        return Ok((StatusCode::BAD_REQUEST, msg).into_response());
+    }
+
+    // Mark the order refunded before giving the money back, so submitting twice can't refund it twice.
+    if !state.db.refund_order(order.id, Utc::now()).await? {
+        return Ok((StatusCode::BAD_REQUEST, "Only paid orders can be refunded.").into_response());
+    }
+    // Free orders have nothing to give back.
+    if let (Some(payment_ref), Some(payments)) = (&order.payment_ref, &state.payments) {
+        if let Err(err) = payments.refund(order.id, payment_ref, order.total).await {
+            // Leave it to be tried again, which the provider won't carry out twice.
+            state.db.unrefund_order(order.id).await?;
+            return Err(err.into());
+        }
+    }
+    Ok(Redirect::to(&format!("/e/{}/orders", order.event_id)).into_response())
+}
+
+/// Process the form to add a ticket tier to an event.
+async fn create_tier_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+    Form(form): Form<TierForm>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Ok((StatusCode::BAD_REQUEST, "Ticket name is required.").into_response());
+    }
+    let price = match parse_money("Price", &form.price) {
+        Ok(price) => price,
+        Err(err) => return Ok((StatusCode::BAD_REQUEST, err).into_response()),
+    };
+    let capacity = match form.capacity.trim() {
+        "" => None,
+        capacity => match capacity.parse::<u32>() {
+            Ok(capacity) => Some(capacity.into()),
+            Err(_) => {
+                return Ok((StatusCode::BAD_REQUEST, "Capacity must be a whole number.").into_response())
+            }
+        },
+    };
+
+    state.db.create_ticket_tier(event.id, name, price, capacity).await?;
+    Ok(Redirect::to(&format!("/e/{}/tickets", event.id)).into_response())
+}
+#[derive(serde::Deserialize)]
+struct TierForm {
+    name: String,
+    price: String,
+    /// Empty for no limit.
+    #[serde(default)]
+    capacity: String,
+}
+
+/// Remove a ticket tier, if nobody has ordered any yet.
+async fn delete_tier_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path((event_id, tier_id)): Path<(i64, i64)>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    if !state.db.delete_ticket_tier(event_id, tier_id).await? {
+        let msg = "Tickets have been ordered in this tier, so it can't be removed.";
+        return Ok((StatusCode::BAD_REQUEST, msg).into_response());
+    }
+    Ok(Redirect::to(&format!("/e/{event_id}/tickets")).into_response())
+}
+
+/// Parse an amount of dollars, like `12.50` or `$12`, into cents.
+///
+/// `name` is used in the user-facing error message.
+pub(crate) fn parse_money(name: &str, value: &str) -> Result<i64, String> {
+    let invalid = || format!("{name} must be an amount like 12.50.");
+    let value = value.trim();
+    let value = value.strip_prefix('$').unwrap_or(value);
+    let (dollars, cents) = value.split_once('.').unwrap_or((value, ""));
+    if dollars.is_empty()
+        || cents.len() > 2
+        || !(dollars.bytes().chain(cents.bytes())).all(|b| b.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+    let dollars: i64 = dollars.parse().map_err(|_| invalid())?;
+    let cents: i64 = format!("{cents:0<2}").parse().map_err(|_| invalid())?;
+    dollars.checked_mul(100).and_then(|d| d.checked_add(cents)).ok_or_else(invalid)
+}
+
+async fn render_tickets(
+    state: &AppState,
+    cookies: &CookieJar,
+    event_id: i64,
+    code: &str,
+    error: Option<String>,
+) -> AppResult<Response> {
+    let user = current_user(state, cookies).await?;
+    let organizer = user.as_ref().is_some_and(|u| u.organizer);
+
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    // Unpublished events don't exist as far as the public is concerned.
+    if !event.status.is_public() && !organizer {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("event", &event);
+    ctx.insert("tiers", &state.db.get_ticket_tiers(event.id).await?);
+    ctx.insert("code", code);
+    ctx.insert("max_quantity", &MAX_TICKETS_PER_ORDER);
+    ctx.insert("user", &user);
+    ctx.insert("organizer", &organizer);
+    ctx.insert("error", &error);
+
+    let html = state.templates.render("tickets.tera.html", &ctx).unwrap();
+    let status = match error {
+        Some(_) => StatusCode::BAD_REQUEST,
+        None => StatusCode::OK,
+    };
+    Ok((status, Html(html)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+    use sqlx::types::Json;
+
+    use super::*;
+    use crate::utils::db::{DiscountKind, PromoCode};
+
+    #[test]
+    fn parses_money() {
+        assert_eq!(parse_money("Price", "12"), Ok(1200));
+        assert_eq!(parse_money("Price", "12.5"), Ok(1250));
+        assert_eq!(parse_money("Price", " $12.05 "), Ok(1205));
+        assert_eq!(parse_money("Price", "0.99"), Ok(99));
+        assert_eq!(parse_money("Price", "12."), Ok(1200));
+    }
+
+    #[test]
+    fn rejects_bad_money() {
+        for value in [
+            "",
+            "$",
+            ".50",
+            "12.345",
+            "-5",
+            "12,50",
+            "1e3",
+            "twelve",
+            "99999999999999999999",
+        ] {
+            assert_eq!(
+                parse_money("Price", value),
+                Err("Price must be an amount like 12.50.".into()),
+                "{value:?}"
+            );
+        }
+    }
+
+    fn promo_code(kind: DiscountKind, amount: i64) -> PromoCode {
+        PromoCode {
+            id: 1,
+            event_id: 1,
+            code: "FRIENDS".into(),
+            kind,
+            amount,
+            max_uses: None,
+            expires_at: None,
+            promoter: String::new(),
+            tier_ids: Json(vec![]),
+            uses: 0,
+            tickets: 0,
+            discounted: 0,
+            revenue: 0,
+            created_at: Local::now(),
+        }
+    }
+
+    #[test]
+    fn percent_discount_rounds_down() {
+        let code = promo_code(DiscountKind::Percent, 15);
+        assert_eq!(code.discount(1000), 150);
+        // 15% of $9.99 is 149.85 cents.
+        assert_eq!(code.discount(999), 149);
+        assert_eq!(code.discount(1), 0);
+        assert_eq!(code.discount(0), 0);
+        assert_eq!(promo_code(DiscountKind::Percent, 33).discount(1000), 330);
+        assert_eq!(promo_code(DiscountKind::Percent, 100).discount(1234), 1234);
+    }
+
+    #[test]
+    fn discount_never_exceeds_subtotal() {
+        assert_eq!(promo_code(DiscountKind::Fixed, 500).discount(1200), 500);
+        assert_eq!(promo_code(DiscountKind::Fixed, 500).discount(300), 300);
+        assert_eq!(promo_code(DiscountKind::Percent, 150).discount(1000), 1000);
+        assert_eq!(promo_code(DiscountKind::Fixed, -100).discount(1000), 0);
+    }
+}
@@ -0,0 +1,266 @@
+//! Event timetables, i.e. who plays on which stage and when.
+//!
+//! Organizers edit the timetable with plain forms on the same page the
+//! public sees. There's also a printable version for the booth.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form,
+};
+use axum_extra::extract::CookieJar;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+use crate::app::{
+    auth::{current_user, is_organizer},
+    events::parse_datetime,
+};
+use crate::utils::db::Event;
+use crate::utils::types::{AppResult, AppRouter, AppState, SharedAppState};
+
+/// Add all `timetables` routes to the router.
+pub fn register_routes(router: AppRouter) -> AppRouter {
+    router
+        .route("/e/:event_id/timetable", get(timetable_page))
+        .route("/e/:event_id/timetable/print", get(print_timetable_page))
+        .route("/e/:event_id/timetable/stages", post(create_stage_form))
+        .route("/e/:event_id/timetable/stages/:stage_id", post(update_stage_form))
+        .route("/e/:event_id/timetable/stages/:stage_id/delete", post(delete_stage_form))
+        .route("/e/:event_id/timetable/slots", post(create_slot_form))
+        .route("/e/:event_id/timetable/slots/:slot_id", post(update_slot_form))
+        .route("/e/:event_id/timetable/slots/:slot_id/delete", post(delete_slot_form))
+}
+
+/// Display the timetable, highlighting whoever is playing right now.
+///
+/// Organizers get forms to edit it.
+async fn timetable_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+) -> AppResult<Response> {
+    render_timetable(&state, &cookies, event_id, "timetable.tera.html", None).await
+}
+
+/// Display the timetable as a plain page for printing.
+async fn print_timetable_page(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+) -> AppResult<Response> {
+    render_timetable(&state, &cookies, event_id, "timetable-print.tera.html", None).await
+}
+
+/// Process the form to add a stage to the timetable.
+async fn create_stage_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+    Form(form): Form<StageForm>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Ok((StatusCode::BAD_REQUEST, "Stage name is required.").into_response());
+    }
+
+    state.db.create_stage(event_id, name).await?;
+    Ok(Redirect::to(&format!("/e/{event_id}/timetable")).into_response())
+}
+
+/// Process the form to rename a stage, or move it within the timetable.
+async fn update_stage_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path((event_id, stage_id)): Path<(i64, i64)>,
+    Form(form): Form<StageForm>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Ok((StatusCode::BAD_REQUEST, "Stage name is required.").into_response());
+    }
+    let Some(position) = form.position else {
+        return Ok((StatusCode::BAD_REQUEST, "Stage position is required.").into_response());
+    };
+
+    state.db.update_stage(event_id, stage_id, name, position).await?;
+    Ok(Redirect::to(&format!("/e/{event_id}/timetable")).into_response())
+}
+#[derive(serde::Deserialize)]
+struct StageForm {
+    name: String,
+    position: Option<i64>,
+}
+
+/// Remove a stage, along with everyone playing on it.
+async fn delete_stage_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path((event_id, stage_id)): Path<(i64, i64)>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    state.db.delete_stage(event_id, stage_id).await?;
+    Ok(Redirect::to(&format!("/e/{event_id}/timetable")).into_response())
+}
+
+/// Process the form to add a set to a stage.
+async fn create_slot_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path(event_id): Path<i64>,
+    Form(form): Form<SlotForm>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let Some(stage_id) = form.stage_id else {
+        return Ok((StatusCode::BAD_REQUEST, "Pick a stage.").into_response());
+    };
+    let (artist, start_at, end_at) = match form.parse(&event, &state) {
+        Ok(slot) => slot,
+        Err(err) => {
+            return render_timetable(&state, &cookies, event_id, "timetable.tera.html", Some(&err)).await
+        }
+    };
+
+    if !state.db.get_stages(event_id).await?.iter().any(|s| s.id == stage_id) {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    if !state.db.create_slot(event_id, stage_id, &artist, start_at, end_at).await? {
+        let err = "That stage already has someone playing at that time.";
+        return render_timetable(&state, &cookies, event_id, "timetable.tera.html", Some(err)).await;
+    }
+    Ok(Redirect::to(&format!("/e/{event_id}/timetable")).into_response())
+}
+
+/// Process the form to change a set's artist or times.
+async fn update_slot_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path((event_id, slot_id)): Path<(i64, i64)>,
+    Form(form): Form<SlotForm>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let (artist, start_at, end_at) = match form.parse(&event, &state) {
+        Ok(slot) => slot,
+        Err(err) => {
+            return render_timetable(&state, &cookies, event_id, "timetable.tera.html", Some(&err)).await
+        }
+    };
+
+    if !state.db.update_slot(event_id, slot_id, &artist, start_at, end_at).await? {
+        let err = "That stage already has someone playing at that time.";
+        return render_timetable(&state, &cookies, event_id, "timetable.tera.html", Some(err)).await;
+    }
+    Ok(Redirect::to(&format!("/e/{event_id}/timetable")).into_response())
+}
+
+/// Remove a set from the timetable.
+async fn delete_slot_form(
+    State(state): State<SharedAppState>,
+    cookies: CookieJar,
+    Path((event_id, slot_id)): Path<(i64, i64)>,
+) -> AppResult<Response> {
+    if !is_organizer(&state, &cookies).await? {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    state.db.delete_slot(event_id, slot_id).await?;
+    Ok(Redirect::to(&format!("/e/{event_id}/timetable")).into_response())
+}
+
+/// Form fields for adding or changing a set.
+///
+/// Times are wall-clock times in the event's timezone, like the event form.
+#[derive(serde::Deserialize)]
+struct SlotForm {
+    /// Only used when adding a set, since sets can't move between stages.
+    stage_id: Option<i64>,
+    artist: String,
+    start_at: String,
+    end_at: String,
+}
+
+impl SlotForm {
+    /// Parse and validate the submitted fields, returning a user-facing message on error.
+    fn parse(
+        &self,
+        event: &Event,
+        state: &AppState,
+    ) -> Result<(String, DateTime<Utc>, DateTime<Utc>), String> {
+        let artist = self.artist.trim();
+        if artist.is_empty() {
+            return Err("Artist is required.".into());
+        }
+
+        let tz: Tz = event.timezone.parse().unwrap_or(state.config.app.timezone);
+        let start_at = parse_datetime("Set start", &self.start_at, tz)?;
+        let end_at = parse_datetime("Set end", &self.end_at, tz)?;
+        if end_at <= start_at {
+            return Err("A set must end after it starts.".into());
+        }
+        Ok((artist.to_string(), start_at, end_at))
+    }
+}
+
+async fn render_timetable(
+    state: &AppState,
+    cookies: &CookieJar,
+    event_id: i64,
+    template: &str,
+    error: Option<&str>,
+) -> AppResult<Response> {
+    let user = current_user(state, cookies).await?;
+    let organizer = user.as_ref().is_some_and(|u| u.organizer);
+
+    let Some(event) = state.db.lookup_event_by_event_id(&event_id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    // Unpublished events don't exist as far as the public is concerned.
+    if !event.status.is_public() && !organizer {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    let stages = state.db.get_stages(event.id).await?;
+    let slots = state.db.get_slots(event.id).await?;
+
+    let now = Utc::now();
+    let now_playing: Vec<i64> = slots
+        .iter()
+        .filter(|slot| slot.start_at <= now && now < slot.end_at)
+        .map(|slot| slot.id)
+        .collect();
+
+    let mut ctx = tera::Context::new();
+    ctx.insert("event", &event);
+    ctx.insert("stages", &stages);
+    ctx.insert("slots", &slots);
+    ctx.insert("now_playing", &now_playing);
+    ctx.insert("organizer", &organizer);
+    ctx.insert("error", &error);
+
+    let html = state.templates.render(template, &ctx).unwrap();
+    let status = match error {
+        Some(_) => StatusCode::BAD_REQUEST,
+        None => StatusCode::OK,
+    };
+    Ok((status, Html(html)).into_response())
+}
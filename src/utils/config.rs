@@ -1,4 +1,5 @@
 use anyhow::{Context, Result};
+use chrono_tz::Tz;
 use lettre::message::Mailbox;
 use std::{net::SocketAddr, path::PathBuf};
 
@@ -20,6 +21,11 @@ pub struct Config {
     pub net: NetConfig,
     pub acme: Option<AcmeConfig>,
     pub email: EmailConfig,
+    /// Without this, only free tickets can be ordered.
+    pub payments: Option<PaymentsConfig>,
+    /// Endpoints to notify when posts are published, given as `[[webhooks]]` tables.
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
 }
 
 /// Webapp configuration.
@@ -27,6 +33,12 @@ pub struct Config {
 pub struct AppConfig {
     pub url: String,
     pub db: PathBuf,
+    /// Directory to store uploaded files in, like event photos.
+    pub uploads: PathBuf,
+    /// Default IANA timezone for new events, e.g. `America/New_York`.
+    pub timezone: Tz,
+    /// Where attendees can leave feedback, linked from post-event emails.
+    pub feedback_url: Option<String>,
 }
 
 /// Networking configuration.
@@ -59,3 +71,30 @@ pub struct EmailConfig {
     /// Mailbox to send email from.
     pub from: Mailbox,
 }
+
+/// Payment provider configuration, picked with `provider = "..."`.
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(tag = "provider", rename_all = "lowercase")]
+pub enum PaymentsConfig {
+    /// Takes no real money, for development. Payments are confirmed by hand on a page of the site itself.
+    Fake,
+    Stripe(StripeConfig),
+}
+
+/// Stripe API credentials.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct StripeConfig {
+    /// Secret API key, starting with `sk_`.
+    pub secret_key: String,
+    /// Signing secret of the webhook endpoint, starting with `whsec_`.
+    pub webhook_secret: String,
+}
+
+/// An endpoint which is sent signed webhooks.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct WebhookConfig {
+    /// `http://` or `https://` URL to `POST` events to.
+    pub url: String,
+    /// Shared secret which webhooks are signed with, so the endpoint can check they came from us.
+    pub secret: String,
+}
@@ -1,10 +1,16 @@
 use std::path::Path;
 
-use anyhow::Result;
-use chrono::{DateTime, Local};
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, TimeDelta, TimeZone as _, Utc};
+use chrono_tz::Tz;
 use lettre::message::Mailbox;
 use rand::{rngs::OsRng, Rng as _};
-use sqlx::{migrate::MigrateDatabase, sqlite::SqliteQueryResult, Error, Sqlite, SqlitePool};
+use sqlx::{
+    migrate::MigrateDatabase, sqlite::SqliteQueryResult, types::Json, Error, QueryBuilder, Sqlite,
+    SqlitePool, Transaction,
+};
+
+use crate::utils::slug;
 
 // +--------------------------------------------------------------------------------+
 // | TODO: Separate the individual types into a `models/` module to reduce clutter. |
@@ -22,33 +28,880 @@ pub struct User {
     pub first_name: String,
     pub last_name: String,
     pub email: String,
+    /// Whether the user can manage events, including unpublished ones.
+    pub organizer: bool,
     pub created_at: String,
 }
 
+impl User {
+    /// The user's email address, with their name attached.
+    pub fn mailbox(&self) -> Result<Mailbox> {
+        let name = format!("{} {}", self.first_name, self.last_name);
+        Ok(Mailbox::new(Some(name), self.email.parse()?))
+    }
+}
+
 #[derive(Debug, sqlx::FromRow, serde::Serialize)]
 pub struct Event {
     pub id: i64,
+    /// Unique URL segment, e.g. `2027-01-01-studio-sessions`.
+    pub slug: String,
     pub title: String,
     pub artist: String,
     pub description: String,
-    pub start_date: DateTime<Local>,
+    pub venue: String,
+    /// IANA timezone the event takes place in, e.g. `America/New_York`.
+    pub timezone: String,
+    pub doors_at: Option<DateTime<Utc>>,
+    pub start_at: DateTime<Utc>,
+    pub end_at: Option<DateTime<Utc>>,
+    pub status: EventStatus,
+    /// When a [`EventStatus::Scheduled`] event should be published.
+    pub publish_at: Option<DateTime<Utc>>,
+    /// New date for a cancelled or postponed event, if one has been set.
+    pub rescheduled_at: Option<DateTime<Utc>>,
+    /// Photo from the event's gallery shown at the top of its page.
+    pub cover_photo_id: Option<i64>,
     pub created_at: DateTime<Local>,
     pub updated_at: DateTime<Local>,
 }
 
+/// Lifecycle state of an [`Event`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, sqlx::Type, serde::Serialize, serde::Deserialize)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum EventStatus {
+    /// Being worked on, and only visible to organizers.
+    Draft,
+    /// Waiting to be published automatically at `publish_at`.
+    Scheduled,
+    Published,
+    Cancelled,
+    Postponed,
+}
+
+impl EventStatus {
+    /// Statuses which are visible to everyone, not just organizers.
+    pub const PUBLIC: [EventStatus; 3] = [
+        EventStatus::Published,
+        EventStatus::Cancelled,
+        EventStatus::Postponed,
+    ];
+
+    pub fn is_public(self) -> bool {
+        Self::PUBLIC.contains(&self)
+    }
+
+    /// Whether an event in this status is allowed to move to `next`.
+    pub fn can_transition_to(self, next: EventStatus) -> bool {
+        use EventStatus::*;
+        matches!(
+            (self, next),
+            (Draft, Scheduled | Published)
+                | (Scheduled, Draft | Published)
+                | (Published, Cancelled | Postponed)
+                | (Postponed, Published | Cancelled)
+        )
+    }
+}
+
+/// Organizer-editable fields of an [`Event`], used to create or update one.
+///
+/// These are also what gets stored in each [`Revision`] of an event.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct EventFields {
+    /// Unique URL segment, or empty to generate one from the date and title.
+    #[serde(default)]
+    pub slug: String,
+    pub title: String,
+    pub artist: String,
+    pub description: String,
+    pub venue: String,
+    pub tags: Vec<String>,
+    pub timezone: Tz,
+    pub doors_at: Option<DateTime<Utc>>,
+    pub start_at: DateTime<Utc>,
+    pub end_at: Option<DateTime<Utc>>,
+}
+
+impl EventFields {
+    /// Copy the editable fields of an existing event.
+    pub fn from_event(event: &Event, tags: Vec<String>) -> Result<Self> {
+        Ok(Self {
+            slug: event.slug.clone(),
+            title: event.title.clone(),
+            artist: event.artist.clone(),
+            description: event.description.clone(),
+            venue: event.venue.clone(),
+            tags,
+            timezone: event
+                .timezone
+                .parse()
+                .map_err(|_| anyhow::anyhow!("invalid timezone={}", event.timezone))?,
+            doors_at: event.doors_at,
+            start_at: event.start_at,
+            end_at: event.end_at,
+        })
+    }
+
+    /// The slug to use if none was picked, e.g. `2027-01-01-studio-sessions`.
+    pub fn default_slug(&self) -> String {
+        default_event_slug(&self.title, self.timezone, self.start_at)
+    }
+}
+
+/// A tag, and how many events have it.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub events: i64,
+}
+
+/// Saved defaults for new events, to pre-fill the create event form.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct EventTemplate {
+    pub id: i64,
+    /// Name of the template itself, e.g. "Studio Sessions".
+    pub name: String,
+    pub title: String,
+    pub artist: String,
+    pub description: String,
+    pub venue: String,
+    /// Comma-separated list of tags.
+    pub tags: String,
+    pub timezone: String,
+    pub created_at: DateTime<Local>,
+}
+
+/// Filters for [`Db::search_events`].
+#[derive(Debug, Default)]
+pub struct EventSearch {
+    /// Full-text search over the title, artist, and description.
+    pub query: Option<String>,
+    /// Only include events which end (or start, if they have no end) after this time.
+    pub ends_after: Option<DateTime<Utc>>,
+    /// Only include events which end (or start, if they have no end) before this time.
+    pub ends_before: Option<DateTime<Utc>>,
+    pub starts_after: Option<DateTime<Utc>>,
+    pub starts_before: Option<DateTime<Utc>>,
+    pub tag: Option<String>,
+    pub venue: Option<String>,
+    pub status: Option<EventStatus>,
+    /// Whether to include drafts and scheduled events.
+    pub include_private: bool,
+    pub sort: EventSort,
+    /// Continue from the end of a previous page.
+    pub cursor: Option<EventCursor>,
+    pub limit: u32,
+}
+
+/// Order of results from [`Db::search_events`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventSort {
+    /// Earliest start time first.
+    #[default]
+    Soonest,
+    /// Latest start time first.
+    Latest,
+    /// Best search match first. Same as `Soonest` when there's no search query.
+    Relevance,
+}
+
+/// A page of results from [`Db::search_events`].
+#[derive(Debug)]
+pub struct EventPage {
+    pub events: Vec<Event>,
+    /// Cursor to fetch the next page, if there are more results.
+    pub next: Option<EventCursor>,
+}
+
+/// Position of the last event on a page, for keyset pagination.
+///
+/// Serialized into URLs as `d_<start_at>_<id>` or `r_<rank>_<id>`, depending on the sort order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventCursor {
+    Date(DateTime<Utc>, i64),
+    Rank(f64, i64),
+}
+
+impl EventCursor {
+    pub fn parse(cursor: &str) -> Option<Self> {
+        let (kind, rest) = cursor.split_once('_')?;
+        let (key, id) = rest.rsplit_once('_')?;
+        let id = id.parse().ok()?;
+        match kind {
+            "d" => Some(Self::Date(DateTime::parse_from_rfc3339(key).ok()?.to_utc(), id)),
+            "r" => Some(Self::Rank(key.parse().ok()?, id)),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for EventCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Date(start_at, id) => write!(f, "d_{}_{id}", start_at.to_rfc3339()),
+            Self::Rank(rank, id) => write!(f, "r_{rank}_{id}"),
+        }
+    }
+}
+
+/// An [`Event`] with its full-text search rank, as returned by [`Db::search_events`].
+#[derive(sqlx::FromRow)]
+struct RankedEvent {
+    #[sqlx(flatten)]
+    event: Event,
+    rank: f64,
+}
+
+/// A number of guest list spots for an event, given to an artist or promoter.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct GuestAllocation {
+    pub id: i64,
+    pub event_id: i64,
+    /// Who the spots are for, e.g. the artist's name.
+    pub name: String,
+    /// How many people can be on the list, including plus-ones.
+    pub quota: i64,
+    /// Secret token for the allocation owner's private link.
+    pub token: String,
+    /// How many spots are taken, including plus-ones.
+    pub used: i64,
+    pub created_at: DateTime<Local>,
+}
+
+/// Someone on the guest list.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct Guest {
+    pub id: i64,
+    pub allocation_id: i64,
+    pub name: String,
+    pub plus_ones: i64,
+    pub checked_in_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Local>,
+}
+
+/// Someone expected at the door, from either an RSVP or the guest list.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct DoorEntry {
+    /// Either `rsvp` or `guest`.
+    pub kind: String,
+    /// The user ID for RSVPs, or guest ID for guests.
+    pub id: i64,
+    pub name: String,
+    pub plus_ones: i64,
+    /// Name of the guest list allocation, for guests.
+    pub list: Option<String>,
+    pub checked_in_at: Option<DateTime<Utc>>,
+}
+
+/// A stage or room at an event, with its own running order.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct Stage {
+    pub id: i64,
+    pub event_id: i64,
+    pub name: String,
+    /// Where the stage is listed in the timetable, lowest first.
+    pub position: i64,
+}
+
+/// An artist's set on a [`Stage`].
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct Slot {
+    pub id: i64,
+    pub stage_id: i64,
+    pub artist: String,
+    pub start_at: DateTime<Utc>,
+    pub end_at: DateTime<Utc>,
+}
+
+/// A photo in an event's gallery.
+///
+/// The image files themselves are stored on disk, see `app::galleries`.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct Photo {
+    pub id: i64,
+    pub event_id: i64,
+    /// Name of the file when it was uploaded.
+    pub filename: String,
+    /// File extension of the original image, e.g. `jpg`.
+    pub format: String,
+    pub width: i64,
+    pub height: i64,
+    /// Whether the photo was taken down, e.g. at the request of someone in it.
+    pub hidden: bool,
+    pub created_at: DateTime<Local>,
+}
+
+/// A kind of ticket for an event, like "Early bird" or "VIP".
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct TicketTier {
+    pub id: i64,
+    pub event_id: i64,
+    pub name: String,
+    /// Price per ticket, in cents.
+    pub price: i64,
+    /// How many tickets can be sold, or `None` for no limit.
+    pub capacity: Option<i64>,
+    /// How many tickets are sold, or held by orders waiting on payment.
+    pub sold: i64,
+    pub created_at: DateTime<Local>,
+}
+
+impl TicketTier {
+    /// How many more tickets can be sold, or `None` for no limit.
+    pub fn remaining(&self) -> Option<i64> {
+        self.capacity.map(|capacity| (capacity - self.sold).max(0))
+    }
+}
+
+/// How a [`PromoCode`] takes money off an order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, sqlx::Type, serde::Serialize, serde::Deserialize)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DiscountKind {
+    /// A percentage of the order.
+    Percent,
+    /// A fixed amount off the order, in cents.
+    Fixed,
+}
+
+/// A code buyers can enter at checkout for a discount.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct PromoCode {
+    pub id: i64,
+    pub event_id: i64,
+    /// Upper case, unique per event.
+    pub code: String,
+    pub kind: DiscountKind,
+    /// Percent off for [`DiscountKind::Percent`], or cents off for [`DiscountKind::Fixed`].
+    pub amount: i64,
+    /// How many orders can use the code, or `None` for no limit.
+    pub max_uses: Option<i64>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Who the code was given to, so sales can be attributed to them.
+    pub promoter: String,
+    /// Tiers the code can be used for, or empty for all of them.
+    pub tier_ids: Json<Vec<i64>>,
+    /// Orders which used the code, including ones waiting on payment.
+    pub uses: i64,
+    /// Tickets in orders which used the code.
+    pub tickets: i64,
+    /// Total taken off orders, in cents.
+    pub discounted: i64,
+    /// Total of paid orders, in cents.
+    pub revenue: i64,
+    pub created_at: DateTime<Local>,
+}
+
+impl PromoCode {
+    /// Check the code can be used on an order for `tier_id`, returning a user-facing message if not.
+    pub fn check(&self, tier_id: i64, now: DateTime<Utc>) -> Result<(), String> {
+        if self.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err(format!("The promo code {} has expired.", self.code));
+        }
+        if self.max_uses.is_some_and(|max_uses| self.uses >= max_uses) {
+            return Err(format!("The promo code {} has been used up.", self.code));
+        }
+        if !self.tier_ids.is_empty() && !self.tier_ids.contains(&tier_id) {
+            return Err(format!("The promo code {} can't be used for these tickets.", self.code));
+        }
+        Ok(())
+    }
+
+    /// How much to take off an order of `subtotal` cents, never more than the order itself.
+    pub fn discount(&self, subtotal: i64) -> i64 {
+        let discount = match self.kind {
+            DiscountKind::Percent => subtotal * self.amount / 100,
+            DiscountKind::Fixed => self.amount,
+        };
+        discount.clamp(0, subtotal)
+    }
+}
+
+/// Fields of a new [`PromoCode`].
+#[derive(Debug)]
+pub struct PromoCodeFields {
+    pub code: String,
+    pub kind: DiscountKind,
+    pub amount: i64,
+    pub max_uses: Option<i64>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub promoter: String,
+    pub tier_ids: Vec<i64>,
+}
+
+/// Lifecycle state of an [`Order`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, sqlx::Type, serde::Serialize, serde::Deserialize)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum OrderStatus {
+    /// Waiting on payment, with the tickets held in the meantime.
+    Pending,
+    Paid,
+    Cancelled,
+    Refunded,
+}
+
+/// Someone buying tickets for an event.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct Order {
+    pub id: i64,
+    pub event_id: i64,
+    pub tier_id: i64,
+    /// Name of the ticket tier.
+    pub tier: String,
+    pub user_id: i64,
+    /// Full name of the buyer.
+    pub buyer: String,
+    pub quantity: i64,
+    /// Price of the tickets before any discount, in cents.
+    pub subtotal: i64,
+    pub discount: i64,
+    pub total: i64,
+    pub promo_code_id: Option<i64>,
+    pub status: OrderStatus,
+    /// Payment provider's ID for the checkout, once the buyer has been sent to pay.
+    pub payment_id: Option<String>,
+    /// Payment provider's ID for the payment itself, used to refund it.
+    pub payment_ref: Option<String>,
+    pub paid_at: Option<DateTime<Utc>>,
+    pub refunded_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Local>,
+}
+
+/// A ticket from a paid [`Order`], one per person.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct Ticket {
+    pub id: i64,
+    pub order_id: i64,
+    /// Secret code to show at the door.
+    pub code: String,
+    pub created_at: DateTime<Local>,
+}
+
+/// Fields of a new [`Order`].
+#[derive(Debug)]
+pub struct NewOrder {
+    pub event_id: i64,
+    pub tier_id: i64,
+    pub user_id: i64,
+    pub quantity: i64,
+    pub subtotal: i64,
+    pub discount: i64,
+    pub total: i64,
+    pub promo_code_id: Option<i64>,
+}
+
+/// A background job, run by the worker in `app::jobs`.
+#[derive(Debug, sqlx::FromRow)]
+pub struct Job {
+    pub id: i64,
+    /// Unique key, so the same job isn't scheduled twice.
+    pub key: String,
+    /// JSON-encoded job description.
+    pub payload: String,
+    pub attempts: i64,
+}
+
+/// What kind of record a [`Revision`] belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, sqlx::Type, serde::Serialize, serde::Deserialize)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum RevisionKind {
+    Event,
+    Post,
+}
+
+/// A snapshot of an event or post, saved every time it's created or edited.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct Revision {
+    pub id: i64,
+    pub kind: RevisionKind,
+    pub record_id: i64,
+    pub editor_id: Option<i64>,
+    /// Full name of the editor, if they're known.
+    pub editor: Option<String>,
+    /// JSON-encoded fields, e.g. [`EventFields`] for events.
+    pub snapshot: String,
+    pub created_at: DateTime<Local>,
+}
+
+/// A single field which differs between two revisions.
+#[derive(Debug, serde::Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: String,
+    pub after: String,
+}
+
+impl Revision {
+    /// List the fields which changed since the `previous` revision, or all fields if this is the first.
+    pub fn diff(&self, previous: Option<&Revision>) -> Result<Vec<FieldChange>> {
+        let after: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&self.snapshot)?;
+        let before: serde_json::Map<String, serde_json::Value> = match previous {
+            Some(previous) => serde_json::from_str(&previous.snapshot)?,
+            None => serde_json::Map::new(),
+        };
+
+        let mut changes = vec![];
+        for (field, value) in &after {
+            let after = display_json(value);
+            let before = before.get(field).map(display_json).unwrap_or_default();
+            if before != after {
+                changes.push(FieldChange { field: field.clone(), before, after });
+            }
+        }
+        Ok(changes)
+    }
+}
+
+/// Format a snapshot field for display, so that equal values compare equal.
+fn display_json(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) => s.clone(),
+        // Lists are things like tags, where the order doesn't matter.
+        serde_json::Value::Array(items) => {
+            let mut items: Vec<String> = items.iter().map(display_json).collect();
+            items.sort_by_key(|item| item.to_lowercase());
+            items.join(", ")
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Fields of a [`Post`], used to create one.
+///
+/// These are also what gets stored in each [`Revision`] of a post.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct PostFields {
+    pub title: String,
+    /// Left empty to generate one from the title.
+    pub slug: String,
+    pub body: String,
+}
+
+impl PostFields {
+    /// The slug to use if none was picked, e.g. `studio-sessions-recap`.
+    pub fn default_slug(&self) -> String {
+        default_post_slug(&self.title)
+    }
+}
+
+/// Someone credited with writing a [`Post`].
+#[derive(Clone, Debug, sqlx::FromRow, serde::Serialize, serde::Deserialize)]
+pub struct PostAuthor {
+    pub user_id: i64,
+    /// Full name of the user.
+    pub name: String,
+}
+
 #[derive(Debug, sqlx::FromRow, serde::Serialize)]
 pub struct Post {
     pub id: i64,
     pub title: String,
     pub slug: String,
+    /// Free-text byline from before authors were linked to users, for posts that couldn't be matched to one.
     pub author: String,
+    /// Users who wrote the post, with the lead author first.
+    pub authors: Json<Vec<PostAuthor>>,
+    /// Markdown source of the post.
     pub body: String,
+    pub status: PostStatus,
+    /// When the post was first published, which is kept if it's unpublished and published again.
+    pub published_at: Option<DateTime<Utc>>,
+    /// When a scheduled post will be published.
+    pub publish_at: Option<DateTime<Utc>>,
+    /// Whether to email the post to newsletter subscribers when it's published on schedule.
+    pub newsletter_on_publish: bool,
+    /// Secret which lets anyone with the link see the post before it's published.
+    pub preview_token: Option<String>,
+    /// Latest revision of the post, which the rendered body is cached against.
+    pub revision_id: Option<i64>,
+    /// Body rendered to HTML, if it's been rendered since the latest revision.
+    pub body_html: Option<String>,
     pub created_at: DateTime<Local>,
     pub updated_at: DateTime<Local>,
 }
 
+impl Post {
+    /// Names of everyone who wrote the post, e.g. `Ann Bee & Cy Dee`.
+    pub fn byline(&self) -> String {
+        match self.authors.as_slice() {
+            [] => self.author.clone(),
+            authors => authors.iter().map(|a| a.name.as_str()).collect::<Vec<_>>().join(" & "),
+        }
+    }
+}
+
+/// Lifecycle state of a [`Post`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, sqlx::Type, serde::Serialize, serde::Deserialize)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum PostStatus {
+    /// Being worked on, and only visible to organizers.
+    Draft,
+    /// Waiting to be published automatically at `publish_at`.
+    Scheduled,
+    Published,
+}
+
+/// Filters for [`Db::search_posts`], which only finds published posts, newest first.
+#[derive(Debug, Default)]
+pub struct PostSearch {
+    /// Only include posts this user wrote or co-wrote.
+    pub author_id: Option<i64>,
+    pub published_after: Option<DateTime<Utc>>,
+    pub published_before: Option<DateTime<Utc>>,
+    /// Continue from the end of a previous page.
+    pub cursor: Option<PostCursor>,
+    pub limit: u32,
+}
+
+/// A page of results from [`Db::search_posts`].
+#[derive(Debug)]
+pub struct PostPage {
+    pub posts: Vec<Post>,
+    /// Cursor to fetch the next page, if there are more results.
+    pub next: Option<PostCursor>,
+}
+
+/// Position of the last post on a page, for keyset pagination.
+///
+/// Serialized into URLs as `<published_at>_<id>`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PostCursor(pub DateTime<Utc>, pub i64);
+
+impl PostCursor {
+    pub fn parse(cursor: &str) -> Option<Self> {
+        let (published_at, id) = cursor.rsplit_once('_')?;
+        Some(Self(
+            DateTime::parse_from_rfc3339(published_at).ok()?.to_utc(),
+            id.parse().ok()?,
+        ))
+    }
+}
+
+impl std::fmt::Display for PostCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}_{}", self.0.to_rfc3339(), self.1)
+    }
+}
+
+/// Number of posts published in a month, for linking to archive pages.
+#[derive(Debug, serde::Serialize)]
+pub struct PostArchiveMonth {
+    pub year: i32,
+    pub month: u32,
+    /// Name of the month, e.g. `March 2027`.
+    pub name: String,
+    pub posts: i64,
+}
+
+/// A file in the media library, for use in posts.
+///
+/// The file itself is stored on disk, see `app::media`.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct Media {
+    pub id: i64,
+    /// Name of the file when it was uploaded, cleaned up for use in its URL.
+    pub filename: String,
+    pub content_type: String,
+    /// Size of the file in bytes.
+    pub size: i64,
+    /// Only set for images.
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    /// Description of an image for people who can't see it.
+    pub alt: String,
+    pub uploaded_by: Option<i64>,
+    /// Full name of whoever uploaded the file, if they still have an account.
+    pub uploader: Option<String>,
+    pub created_at: DateTime<Local>,
+}
+
+/// A post being emailed to newsletter subscribers, with how far along it is.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct Newsletter {
+    pub id: i64,
+    pub post_id: i64,
+    /// Organizer who sent it, or `None` if it went out when the post was published on schedule.
+    pub sent_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    /// When the last email was sent, or `None` while some are still pending.
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Number of recipients with each [`RecipientStatus`].
+    pub pending: i64,
+    pub sent: i64,
+    pub failed: i64,
+    pub skipped: i64,
+}
+
+/// Whether a [`Newsletter`] has been emailed to someone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, sqlx::Type, serde::Serialize, serde::Deserialize)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum RecipientStatus {
+    Pending,
+    /// Handed to the mail server, but not confirmed yet.
+    Sending,
+    Sent,
+    /// The mail server refused it, or sending was interrupted and it's unknown whether it went out.
+    Failed,
+    /// They unsubscribed before their turn came.
+    Skipped,
+}
+
+/// Someone a [`Newsletter`] is being emailed to.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct NewsletterRecipient {
+    pub user_id: i64,
+    pub first_name: String,
+    pub last_name: String,
+    pub email: String,
+    pub status: RecipientStatus,
+    pub error: Option<String>,
+    pub sent_at: Option<DateTime<Utc>>,
+    /// Secret token for unsubscribing with one click, if they're still subscribed.
+    pub unsubscribe_token: Option<String>,
+}
+
+impl NewsletterRecipient {
+    /// The recipient's email address, with their name attached.
+    pub fn mailbox(&self) -> Result<Mailbox> {
+        let name = format!("{} {}", self.first_name, self.last_name);
+        Ok(Mailbox::new(Some(name), self.email.parse()?))
+    }
+}
+
+/// A comment on a post or event, or a reply to one.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct Comment {
+    pub id: i64,
+    pub target: CommentTarget,
+    /// ID of the post or event it's on.
+    pub target_id: i64,
+    /// Comment this is a reply to, which is always a top-level comment.
+    pub parent_id: Option<i64>,
+    pub user_id: i64,
+    /// Full name of whoever wrote it.
+    pub author: String,
+    /// Markdown source of the comment.
+    pub body: String,
+    pub status: CommentStatus,
+    pub created_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+    /// When it was deleted. Deleted comments stay in the database, to keep their replies in place.
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Title of the post or event it's on.
+    pub target_title: String,
+    /// Link to the post or event it's on.
+    pub target_url: String,
+}
+
+/// What a [`Comment`] is on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, sqlx::Type, serde::Serialize, serde::Deserialize)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum CommentTarget {
+    Event,
+    Post,
+}
+
+/// Whether a [`Comment`] can be seen by everyone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, sqlx::Type, serde::Serialize, serde::Deserialize)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum CommentStatus {
+    /// Waiting in the moderation queue, and only visible to its author and organizers.
+    Pending,
+    Approved,
+    /// Taken down by an organizer.
+    Hidden,
+}
+
+/// A user who isn't allowed to comment any more.
+#[derive(Debug, sqlx::FromRow, serde::Serialize)]
+pub struct CommentBan {
+    pub user_id: i64,
+    pub name: String,
+    pub email: String,
+    /// Full name of the organizer who banned them, if they still have an account.
+    pub banned_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Start of a query for [`PromoCode`]s, with their usage totals.
+const PROMO_CODE_SELECT: &str = "\
+    SELECT p.*, \
+        (SELECT json_group_array(tier_id) FROM promo_code_tiers WHERE promo_code_id = p.id) AS tier_ids, \
+        (SELECT COUNT(*) FROM orders WHERE promo_code_id = p.id AND status IN ('pending', 'paid')) AS uses, \
+        (SELECT COALESCE(SUM(quantity), 0) FROM orders \
+         WHERE promo_code_id = p.id AND status IN ('pending', 'paid')) AS tickets, \
+        (SELECT COALESCE(SUM(discount), 0) FROM orders \
+         WHERE promo_code_id = p.id AND status IN ('pending', 'paid')) AS discounted, \
+        (SELECT COALESCE(SUM(total), 0) FROM orders WHERE promo_code_id = p.id AND status = 'paid') AS revenue \
+    FROM promo_codes p";
+
+/// Start of a query for [`Order`]s, with the tier and buyer names.
+const ORDER_SELECT: &str = "\
+    SELECT o.*, t.name AS tier, u.first_name || ' ' || u.last_name AS buyer \
+    FROM orders o \
+    JOIN ticket_tiers t ON t.id = o.tier_id \
+    JOIN users u ON u.id = o.user_id";
+
+/// Start of a query for [`Post`]s, with the rendered body of their latest revision.
+const POST_SELECT: &str = "\
+    SELECT p.*, r.id AS revision_id, r.html AS body_html, \
+        (SELECT json_group_array(json_object('user_id', a.user_id, 'name', a.name)) FROM ( \
+            SELECT a.user_id, u.first_name || ' ' || u.last_name AS name \
+            FROM post_authors a JOIN users u ON u.id = a.user_id \
+            WHERE a.post_id = p.id ORDER BY a.position \
+        ) a) AS authors \
+    FROM posts p \
+    LEFT JOIN revisions r ON r.id = \
+        (SELECT MAX(id) FROM revisions WHERE kind = 'post' AND record_id = p.id)";
+
+/// Start of a query for [`Newsletter`]s, with how many recipients are at each stage.
+const NEWSLETTER_SELECT: &str = "\
+    SELECT n.*, \
+        (SELECT COUNT(*) FROM newsletter_recipients WHERE newsletter_id = n.id AND status IN ('pending', 'sending')) \
+            AS pending, \
+        (SELECT COUNT(*) FROM newsletter_recipients WHERE newsletter_id = n.id AND status = 'sent') AS sent, \
+        (SELECT COUNT(*) FROM newsletter_recipients WHERE newsletter_id = n.id AND status = 'failed') AS failed, \
+        (SELECT COUNT(*) FROM newsletter_recipients WHERE newsletter_id = n.id AND status = 'skipped') AS skipped \
+    FROM newsletters n";
+
+/// Start of a query for [`NewsletterRecipient`]s, with their current details.
+const NEWSLETTER_RECIPIENT_SELECT: &str = "\
+    SELECT r.*, u.first_name, u.last_name, u.email, s.token AS unsubscribe_token \
+    FROM newsletter_recipients r \
+    JOIN users u ON u.id = r.user_id \
+    LEFT JOIN newsletter_subscribers s ON s.user_id = r.user_id";
+
+/// Start of a query for [`Comment`]s, with the author's name and what they're on.
+const COMMENT_SELECT: &str = "\
+    SELECT c.*, u.first_name || ' ' || u.last_name AS author, \
+        COALESCE(p.title, e.title, '') AS target_title, \
+        CASE c.target WHEN 'post' THEN '/p/' || COALESCE(p.slug, '') ELSE '/e/' || COALESCE(e.slug, c.target_id) END \
+            AS target_url \
+    FROM comments c \
+    JOIN users u ON u.id = c.user_id \
+    LEFT JOIN posts p ON c.target = 'post' AND p.id = c.target_id \
+    LEFT JOIN events e ON c.target = 'event' AND e.id = c.target_id";
+
+/// Start of a query for [`Media`], with the uploader's name.
+const MEDIA_SELECT: &str = "\
+    SELECT m.*, u.first_name || ' ' || u.last_name AS uploader \
+    FROM media m \
+    LEFT JOIN users u ON u.id = m.uploaded_by";
+
 impl Db {
-    pub async fn connect(file: &Path) -> Result<Self> {
+    /// Open the database, creating it or upgrading its tables as needed. The `timezone`
+    /// is assumed for events saved before they recorded their own.
+    pub async fn connect(file: &Path, timezone: Tz) -> Result<Self> {
         let url = format!("sqlite://{}", file.display());
         if !Sqlite::database_exists(&url).await? {
             Sqlite::create_database(&url).await?;
@@ -56,22 +909,26 @@ impl Db {
         let pool = SqlitePool::connect(&url).await?;
 
         let db = Self { pool };
-        db.migrate().await?;
+        db.migrate(timezone).await?;
         Ok(db)
     }
 
-    async fn migrate(&self) -> Result<()> {
+    async fn migrate(&self, timezone: Tz) -> Result<()> {
         sqlx::query(
             "CREATE TABLE IF NOT EXISTS users ( \
                 id INTEGER PRIMARY KEY NOT NULL, \
                 first_name TEXT NOT NULL, \
                 last_name TEXT NOT NULL, \
                 email TEXT NOT NULL, \
+                organizer BOOLEAN NOT NULL DEFAULT FALSE, \
                 created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP \
             )",
         )
         .execute(&self.pool)
         .await?;
+        let mut tx = self.pool.begin().await?;
+        add_column(&mut tx, "users", "organizer", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+        tx.commit().await?;
 
         sqlx::query(
             "CREATE TABLE IF NOT EXISTS login_tokens ( \
@@ -99,10 +956,19 @@ impl Db {
         sqlx::query(
             "CREATE TABLE IF NOT EXISTS events ( \
                 id INTEGER PRIMARY KEY NOT NULL, \
+                slug TEXT NOT NULL UNIQUE, \
                 title TEXT NOT NULL, \
                 artist TEXT NOT NULL, \
                 description TEXT NOT NULL, \
-                start_date TIMESTAMP NOT NULL, \
+                venue TEXT NOT NULL DEFAULT '', \
+                timezone TEXT NOT NULL, \
+                doors_at TIMESTAMP, \
+                start_at TIMESTAMP NOT NULL, \
+                end_at TIMESTAMP, \
+                status TEXT NOT NULL DEFAULT 'draft', \
+                publish_at TIMESTAMP, \
+                rescheduled_at TIMESTAMP, \
+                cover_photo_id INTEGER REFERENCES photos(id) ON DELETE SET NULL, \
                 created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, \
                 updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP \
             )",
@@ -110,183 +976,2747 @@ impl Db {
         .execute(&self.pool)
         .await?;
 
+        // Bring events tables from older versions up to date.
+        let mut tx = self.pool.begin().await?;
+        if has_column(&mut tx, "events", "start_date").await? {
+            // Events used to have only a `start_date`, the wall-clock time typed into the form.
+            add_column(&mut tx, "events", "timezone", "TEXT NOT NULL DEFAULT ''").await?;
+            add_column(&mut tx, "events", "doors_at", "TIMESTAMP").await?;
+            add_column(&mut tx, "events", "start_at", "TIMESTAMP").await?;
+            add_column(&mut tx, "events", "end_at", "TIMESTAMP").await?;
+            let events: Vec<(i64, String)> =
+                sqlx::query_as("SELECT id, start_date FROM events").fetch_all(&mut *tx).await?;
+            for (id, start_date) in events {
+                let start_at = legacy_start_date(&start_date, timezone)
+                    .with_context(|| format!("converting start_date {start_date:?} of event {id}"))?;
+                sqlx::query("UPDATE events SET timezone = ?, start_at = ? WHERE id = ?")
+                    .bind(timezone.name())
+                    .bind(start_at)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            sqlx::query("ALTER TABLE events DROP COLUMN start_date")
+                .execute(&mut *tx)
+                .await?;
+        }
+        // Events from before drafts were all public.
+        if add_column(&mut tx, "events", "status", "TEXT NOT NULL DEFAULT 'draft'").await? {
+            sqlx::query("UPDATE events SET status = 'published'").execute(&mut *tx).await?;
+        }
+        add_column(&mut tx, "events", "publish_at", "TIMESTAMP").await?;
+        add_column(&mut tx, "events", "rescheduled_at", "TIMESTAMP").await?;
+        add_column(&mut tx, "events", "venue", "TEXT NOT NULL DEFAULT ''").await?;
+        if add_column(&mut tx, "events", "slug", "TEXT NOT NULL DEFAULT ''").await? {
+            let events: Vec<(i64, String, String, DateTime<Utc>)> =
+                sqlx::query_as("SELECT id, title, timezone, start_at FROM events ORDER BY id")
+                    .fetch_all(&mut *tx)
+                    .await?;
+            for (id, title, timezone, start_at) in events {
+                let timezone = timezone
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("invalid timezone={timezone} of event {id}"))?;
+                let base = default_event_slug(&title, timezone, start_at);
+                let slug = unique_event_slug(&mut tx, &base, Some(id)).await?;
+                sqlx::query("UPDATE events SET slug = ? WHERE id = ?")
+                    .bind(slug)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            sqlx::query("CREATE UNIQUE INDEX events_slug ON events (slug)")
+                .execute(&mut *tx)
+                .await?;
+        }
+        add_column(
+            &mut tx,
+            "events",
+            "cover_photo_id",
+            "INTEGER REFERENCES photos(id) ON DELETE SET NULL",
+        )
+        .await?;
+        tx.commit().await?;
+
+        // Slugs events used to have, so old links keep working.
         sqlx::query(
-            "CREATE TABLE IF NOT EXISTS posts ( \
-                id INTEGER PRIMARY KEY NOT NULL, \
-                title TEXT NOT NULL, \
-                slug TEXT NOT NULL, \
-                author TEXT NOT NULL, \
-                body TEXT NOT NULL, \
-                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, \
-                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP \
+            "CREATE TABLE IF NOT EXISTS event_old_slugs ( \
+                slug TEXT PRIMARY KEY NOT NULL, \
+                event_id INTEGER NOT NULL, \
+                FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE \
             )",
         )
         .execute(&self.pool)
         .await?;
 
-        Ok(())
-    }
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS event_tags ( \
+                event_id INTEGER NOT NULL, \
+                tag TEXT NOT NULL COLLATE NOCASE, \
+                PRIMARY KEY (event_id, tag), \
+                FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
 
-    pub async fn create_user(&self, first_name: &str, last_name: &str, email: &str) -> Result<i64> {
-        let row = sqlx::query("INSERT INTO users (first_name, last_name, email) VALUES (?, ?, ?)")
-            .bind(first_name)
-            .bind(last_name)
-            .bind(email)
-            .execute(&self.pool)
-            .await?;
-        Ok(row.last_insert_rowid())
-    }
-    pub async fn lookup_user_by_email(&self, email: &Mailbox) -> Result<Option<User>> {
-        let row = sqlx::query_as::<_, User>("SELECT * FROM users WHERE email = ?")
-            .bind(email.email.to_string())
-            .fetch_optional(&self.pool)
-            .await?;
-        Ok(row)
-    }
-    pub async fn lookup_user_by_login_token(&self, token: &str) -> Result<Option<User>> {
-        let row = sqlx::query_as::<_, User>(
-            "SELECT u.* \
+        // Full-text search index over events, kept in sync with the `events` table by triggers.
+        sqlx::query(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS events_fts USING fts5( \
+                title, artist, description, \
+                content='events', content_rowid='id' \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "CREATE TRIGGER IF NOT EXISTS events_fts_insert AFTER INSERT ON events BEGIN \
+                INSERT INTO events_fts (rowid, title, artist, description) \
+                VALUES (new.id, new.title, new.artist, new.description); \
+            END",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "CREATE TRIGGER IF NOT EXISTS events_fts_delete AFTER DELETE ON events BEGIN \
+                INSERT INTO events_fts (events_fts, rowid, title, artist, description) \
+                VALUES ('delete', old.id, old.title, old.artist, old.description); \
+            END",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "CREATE TRIGGER IF NOT EXISTS events_fts_update AFTER UPDATE ON events BEGIN \
+                INSERT INTO events_fts (events_fts, rowid, title, artist, description) \
+                VALUES ('delete', old.id, old.title, old.artist, old.description); \
+                INSERT INTO events_fts (rowid, title, artist, description) \
+                VALUES (new.id, new.title, new.artist, new.description); \
+            END",
+        )
+        .execute(&self.pool)
+        .await?;
+        // Cheap at our scale, and picks up any events which predate the index.
+        sqlx::query("INSERT INTO events_fts (events_fts) VALUES ('rebuild')")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS revisions ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                kind TEXT NOT NULL, \
+                record_id INTEGER NOT NULL, \
+                editor_id INTEGER REFERENCES users(id) ON DELETE SET NULL, \
+                snapshot TEXT NOT NULL, \
+                html TEXT, \
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS revisions_record ON revisions (kind, record_id)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS event_templates ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                name TEXT NOT NULL, \
+                title TEXT NOT NULL, \
+                artist TEXT NOT NULL, \
+                description TEXT NOT NULL, \
+                venue TEXT NOT NULL, \
+                tags TEXT NOT NULL, \
+                timezone TEXT NOT NULL, \
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS rsvps ( \
+                event_id INTEGER NOT NULL, \
+                user_id INTEGER NOT NULL, \
+                checked_in_at TIMESTAMP, \
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, \
+                PRIMARY KEY (event_id, user_id), \
+                FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE, \
+                FOREIGN KEY (user_id) REFERENCES users(id) \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS guest_allocations ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                event_id INTEGER NOT NULL, \
+                name TEXT NOT NULL, \
+                quota INTEGER NOT NULL, \
+                token TEXT NOT NULL UNIQUE, \
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, \
+                FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS guests ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                allocation_id INTEGER NOT NULL, \
+                name TEXT NOT NULL, \
+                plus_ones INTEGER NOT NULL DEFAULT 0, \
+                checked_in_at TIMESTAMP, \
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, \
+                FOREIGN KEY (allocation_id) REFERENCES guest_allocations(id) ON DELETE CASCADE \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS stages ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                event_id INTEGER NOT NULL, \
+                name TEXT NOT NULL, \
+                position INTEGER NOT NULL, \
+                FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS slots ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                stage_id INTEGER NOT NULL, \
+                artist TEXT NOT NULL, \
+                start_at TIMESTAMP NOT NULL, \
+                end_at TIMESTAMP NOT NULL, \
+                FOREIGN KEY (stage_id) REFERENCES stages(id) ON DELETE CASCADE \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS photos ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                event_id INTEGER NOT NULL, \
+                filename TEXT NOT NULL, \
+                format TEXT NOT NULL, \
+                width INTEGER NOT NULL, \
+                height INTEGER NOT NULL, \
+                hidden BOOLEAN NOT NULL DEFAULT FALSE, \
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, \
+                FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS ticket_tiers ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                event_id INTEGER NOT NULL, \
+                name TEXT NOT NULL, \
+                price INTEGER NOT NULL, \
+                capacity INTEGER, \
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, \
+                FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS promo_codes ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                event_id INTEGER NOT NULL, \
+                code TEXT NOT NULL, \
+                kind TEXT NOT NULL, \
+                amount INTEGER NOT NULL, \
+                max_uses INTEGER, \
+                expires_at TIMESTAMP, \
+                promoter TEXT NOT NULL DEFAULT '', \
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, \
+                UNIQUE (event_id, code), \
+                FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Which tiers a promo code is limited to. Codes without any apply to every tier.
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS promo_code_tiers ( \
+                promo_code_id INTEGER NOT NULL, \
+                tier_id INTEGER NOT NULL, \
+                PRIMARY KEY (promo_code_id, tier_id), \
+                FOREIGN KEY (promo_code_id) REFERENCES promo_codes(id) ON DELETE CASCADE, \
+                FOREIGN KEY (tier_id) REFERENCES ticket_tiers(id) ON DELETE CASCADE \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS orders ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                event_id INTEGER NOT NULL, \
+                tier_id INTEGER NOT NULL, \
+                user_id INTEGER NOT NULL, \
+                quantity INTEGER NOT NULL, \
+                subtotal INTEGER NOT NULL, \
+                discount INTEGER NOT NULL DEFAULT 0, \
+                total INTEGER NOT NULL, \
+                promo_code_id INTEGER, \
+                status TEXT NOT NULL DEFAULT 'pending', \
+                payment_id TEXT UNIQUE, \
+                payment_ref TEXT, \
+                paid_at TIMESTAMP, \
+                refunded_at TIMESTAMP, \
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, \
+                FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE, \
+                FOREIGN KEY (tier_id) REFERENCES ticket_tiers(id), \
+                FOREIGN KEY (user_id) REFERENCES users(id), \
+                FOREIGN KEY (promo_code_id) REFERENCES promo_codes(id) \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS orders_tier ON orders (tier_id)")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS orders_promo_code ON orders (promo_code_id)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS tickets ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                order_id INTEGER NOT NULL, \
+                code TEXT NOT NULL UNIQUE, \
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, \
+                FOREIGN KEY (order_id) REFERENCES orders(id) ON DELETE CASCADE \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS jobs ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                key TEXT NOT NULL UNIQUE, \
+                payload TEXT NOT NULL, \
+                run_at TIMESTAMP NOT NULL, \
+                status TEXT NOT NULL DEFAULT 'pending', \
+                attempts INTEGER NOT NULL DEFAULT 0, \
+                last_error TEXT, \
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Keys of emails which have already been sent, so jobs which are retried
+        // or interrupted part way through don't send anything twice.
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS sent_emails ( \
+                key TEXT PRIMARY KEY NOT NULL, \
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS posts ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                title TEXT NOT NULL, \
+                slug TEXT NOT NULL, \
+                author TEXT NOT NULL DEFAULT '', \
+                body TEXT NOT NULL, \
+                status TEXT NOT NULL DEFAULT 'draft', \
+                published_at TIMESTAMP, \
+                publish_at TIMESTAMP, \
+                newsletter_on_publish BOOLEAN NOT NULL DEFAULT FALSE, \
+                preview_token TEXT, \
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, \
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Bring posts tables from older versions up to date.
+        let mut tx = self.pool.begin().await?;
+        add_column(&mut tx, "posts", "published_at", "TIMESTAMP").await?;
+        // Posts from before drafts were all public.
+        if add_column(&mut tx, "posts", "status", "TEXT NOT NULL DEFAULT 'draft'").await? {
+            sqlx::query("UPDATE posts SET status = 'published', published_at = created_at")
+                .execute(&mut *tx)
+                .await?;
+        }
+        add_column(&mut tx, "posts", "publish_at", "TIMESTAMP").await?;
+        add_column(&mut tx, "posts", "newsletter_on_publish", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+        add_column(&mut tx, "posts", "preview_token", "TEXT").await?;
+        tx.commit().await?;
+
+        // Everyone credited on a post, with the lead author at position 0.
+        let mut tx = self.pool.begin().await?;
+        let existed: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'post_authors')",
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS post_authors ( \
+                post_id INTEGER NOT NULL, \
+                user_id INTEGER NOT NULL, \
+                position INTEGER NOT NULL, \
+                PRIMARY KEY (post_id, user_id), \
+                FOREIGN KEY (post_id) REFERENCES posts(id) ON DELETE CASCADE, \
+                FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE \
+            )",
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS post_authors_user ON post_authors (user_id)")
+            .execute(&mut *tx)
+            .await?;
+        // Link posts from before authors were users to the one organizer with the same name, if
+        // there is one. Only organizers, so nobody can sign up under a name to claim its posts.
+        // This is only done once, so posts whose authors are later deleted stay uncredited.
+        if !existed {
+            sqlx::query(
+                "INSERT INTO post_authors (post_id, user_id, position) \
+                 SELECT p.id, MIN(u.id), 0 FROM posts p \
+                 JOIN users u ON LOWER(TRIM(p.author)) = LOWER(u.first_name || ' ' || u.last_name) \
+                 WHERE u.organizer = 1 \
+                 GROUP BY p.id HAVING COUNT(*) = 1",
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        // Older databases allowed blank and duplicate slugs, so give those posts new ones
+        // from their titles before making slugs unique.
+        let mut tx = self.pool.begin().await?;
+        let indexed: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'index' AND name = 'posts_slug')",
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if !indexed {
+            let posts: Vec<(i64, String)> = sqlx::query_as(
+                "SELECT id, title FROM posts \
+                 WHERE slug = '' OR id NOT IN (SELECT MIN(id) FROM posts GROUP BY slug) \
+                 ORDER BY id",
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+            for (id, title) in posts {
+                let slug = unique_post_slug(&mut tx, &default_post_slug(&title), Some(id)).await?;
+                sqlx::query("UPDATE posts SET slug = ? WHERE id = ?")
+                    .bind(slug)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            sqlx::query("CREATE UNIQUE INDEX posts_slug ON posts (slug)")
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        // Posts from before preview links need a secret of their own.
+        sqlx::query(
+            "UPDATE posts SET preview_token = lower(hex(randomblob(16))) WHERE preview_token IS NULL",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Slugs posts used to have, so old links keep working.
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS post_old_slugs ( \
+                slug TEXT PRIMARY KEY NOT NULL, \
+                post_id INTEGER NOT NULL, \
+                FOREIGN KEY (post_id) REFERENCES posts(id) ON DELETE CASCADE \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS media ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                filename TEXT NOT NULL, \
+                content_type TEXT NOT NULL, \
+                size INTEGER NOT NULL, \
+                width INTEGER, \
+                height INTEGER, \
+                alt TEXT NOT NULL DEFAULT '', \
+                uploaded_by INTEGER, \
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, \
+                FOREIGN KEY (uploaded_by) REFERENCES users(id) ON DELETE SET NULL \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Users who opted in to the newsletter, with a secret for unsubscribing from emails.
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS newsletter_subscribers ( \
+                user_id INTEGER PRIMARY KEY NOT NULL, \
+                token TEXT NOT NULL UNIQUE, \
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, \
+                FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS newsletters ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                post_id INTEGER NOT NULL, \
+                sent_by INTEGER, \
+                created_at TIMESTAMP NOT NULL, \
+                finished_at TIMESTAMP, \
+                FOREIGN KEY (post_id) REFERENCES posts(id) ON DELETE CASCADE, \
+                FOREIGN KEY (sent_by) REFERENCES users(id) ON DELETE SET NULL \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Everyone who was subscribed when a newsletter started going out.
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS newsletter_recipients ( \
+                newsletter_id INTEGER NOT NULL, \
+                user_id INTEGER NOT NULL, \
+                status TEXT NOT NULL DEFAULT 'pending', \
+                error TEXT, \
+                sent_at TIMESTAMP, \
+                PRIMARY KEY (newsletter_id, user_id), \
+                FOREIGN KEY (newsletter_id) REFERENCES newsletters(id) ON DELETE CASCADE, \
+                FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Comments on posts and events, threaded one level deep.
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS comments ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                target TEXT NOT NULL, \
+                target_id INTEGER NOT NULL, \
+                parent_id INTEGER, \
+                user_id INTEGER NOT NULL, \
+                body TEXT NOT NULL, \
+                status TEXT NOT NULL DEFAULT 'pending', \
+                created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP, \
+                edited_at TIMESTAMP, \
+                deleted_at TIMESTAMP, \
+                FOREIGN KEY (parent_id) REFERENCES comments(id) ON DELETE CASCADE, \
+                FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS comments_target ON comments (target, target_id)")
+            .execute(&self.pool)
+            .await?;
+
+        // Users who aren't allowed to comment.
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS comment_bans ( \
+                user_id INTEGER PRIMARY KEY NOT NULL, \
+                banned_by INTEGER, \
+                created_at TIMESTAMP NOT NULL, \
+                FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE, \
+                FOREIGN KEY (banned_by) REFERENCES users(id) ON DELETE SET NULL \
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn create_user(&self, first_name: &str, last_name: &str, email: &str) -> Result<i64> {
+        let row = sqlx::query("INSERT INTO users (first_name, last_name, email) VALUES (?, ?, ?)")
+            .bind(first_name)
+            .bind(last_name)
+            .bind(email)
+            .execute(&self.pool)
+            .await?;
+        Ok(row.last_insert_rowid())
+    }
+    /// Everyone who can manage the site, e.g. to credit as co-authors.
+    pub async fn get_organizers(&self) -> Result<Vec<User>> {
+        let users = sqlx::query_as::<_, User>(
+            "SELECT * FROM users WHERE organizer = 1 ORDER BY first_name COLLATE NOCASE, last_name COLLATE NOCASE",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(users)
+    }
+    pub async fn lookup_user_by_email(&self, email: &Mailbox) -> Result<Option<User>> {
+        let row = sqlx::query_as::<_, User>("SELECT * FROM users WHERE email = ?")
+            .bind(email.email.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row)
+    }
+    pub async fn lookup_user_by_id(&self, id: i64) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(user)
+    }
+    pub async fn lookup_user_by_login_token(&self, token: &str) -> Result<Option<User>> {
+        let row = sqlx::query_as::<_, User>(
+            "SELECT u.* \
              FROM login_tokens t \
              LEFT JOIN users u on u.email = t.email \
              WHERE t.token = ?",
         )
-        .bind(token)
-        .fetch_optional(&self.pool)
+        .bind(token)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row)
+    }
+    pub async fn lookup_user_from_session_token(&self, token: &str) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            "SELECT u.* \
+             FROM session_tokens t \
+             JOIN users u on u.id = t.user_id \
+             WHERE token = ?",
+        )
+        .bind(token)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(user)
+    }
+
+    pub async fn create_session_token(&self, user_id: i64) -> Result<String> {
+        let token = format!("{:08x}", OsRng.gen::<u64>());
+
+        sqlx::query("INSERT INTO session_tokens (user_id, token) VALUES (?, ?)")
+            .bind(user_id)
+            .bind(&token)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(token)
+    }
+    pub async fn create_login_token(&self, email: &Mailbox) -> Result<String> {
+        let token = format!("{:08x}", OsRng.gen::<u64>());
+
+        sqlx::query("INSERT INTO login_tokens (email, token) VALUES (?, ?)")
+            .bind(email.email.to_string())
+            .bind(&token)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(token)
+    }
+    pub async fn lookup_email_by_login_token(&self, token: &str) -> Result<Option<String>> {
+        let row = sqlx::query_as::<_, (String,)>("SELECT email FROM login_tokens WHERE token = ?")
+            .bind(token)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|r| r.0))
+    }
+    // Lookup Event by id
+    pub async fn lookup_event_by_event_id(&self, id: &i64) -> Result<Option<Event>> {
+        let event = sqlx::query_as::<_, Event>(
+            "SELECT e.* \
+            FROM events e \
+            WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(event)
+    }
+    // Lookup Event by its current slug
+    pub async fn lookup_event_by_slug(&self, slug: &str) -> Result<Option<Event>> {
+        let event = sqlx::query_as::<_, Event>("SELECT * FROM events WHERE slug = ?")
+            .bind(slug)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(event)
+    }
+    // Lookup Event by a slug it used to have
+    pub async fn lookup_event_by_old_slug(&self, slug: &str) -> Result<Option<Event>> {
+        let event = sqlx::query_as::<_, Event>(
+            "SELECT e.* FROM events e JOIN event_old_slugs o ON o.event_id = e.id WHERE o.slug = ?",
+        )
+        .bind(slug)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(event)
+    }
+    // Check whether an Event other than `except_id` already has a slug
+    pub async fn is_event_slug_taken(&self, slug: &str, except_id: Option<i64>) -> Result<bool> {
+        let taken = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM events WHERE slug = ? AND id IS NOT ?)",
+        )
+        .bind(slug)
+        .bind(except_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(taken)
+    }
+    // Search Events
+    pub async fn search_events(&self, search: &EventSearch) -> Result<EventPage> {
+        let query = search.query.as_deref().map(fts_query).filter(|q| !q.is_empty());
+        let sort = match search.sort {
+            EventSort::Relevance if query.is_none() => EventSort::Soonest,
+            sort => sort,
+        };
+
+        let mut q = QueryBuilder::<Sqlite>::new("SELECT e.*, ");
+        match &query {
+            Some(query) => {
+                q.push("events_fts.rank AS rank FROM events e JOIN events_fts ON events_fts.rowid = e.id");
+                q.push(" WHERE events_fts MATCH ").push_bind(query.clone());
+            }
+            None => {
+                q.push("0.0 AS rank FROM events e WHERE TRUE");
+            }
+        }
+        if let Some(ends_after) = search.ends_after {
+            q.push(" AND COALESCE(e.end_at, e.start_at) >= ").push_bind(ends_after);
+        }
+        if let Some(ends_before) = search.ends_before {
+            q.push(" AND COALESCE(e.end_at, e.start_at) < ").push_bind(ends_before);
+        }
+        if let Some(starts_after) = search.starts_after {
+            q.push(" AND e.start_at >= ").push_bind(starts_after);
+        }
+        if let Some(starts_before) = search.starts_before {
+            q.push(" AND e.start_at < ").push_bind(starts_before);
+        }
+        if let Some(tag) = &search.tag {
+            q.push(" AND EXISTS (SELECT 1 FROM event_tags t WHERE t.event_id = e.id AND t.tag = ")
+                .push_bind(tag.clone())
+                .push(")");
+        }
+        if let Some(venue) = &search.venue {
+            q.push(" AND e.venue = ").push_bind(venue.clone()).push(" COLLATE NOCASE");
+        }
+        if let Some(status) = search.status {
+            q.push(" AND e.status = ").push_bind(status);
+        }
+        if !search.include_private {
+            q.push(" AND e.status IN ('published', 'cancelled', 'postponed')");
+        }
+
+        match (sort, search.cursor) {
+            (EventSort::Soonest, Some(EventCursor::Date(start_at, id))) => {
+                q.push(" AND (e.start_at, e.id) > (")
+                    .push_bind(start_at)
+                    .push(", ")
+                    .push_bind(id)
+                    .push(")");
+            }
+            (EventSort::Latest, Some(EventCursor::Date(start_at, id))) => {
+                q.push(" AND (e.start_at, e.id) < (")
+                    .push_bind(start_at)
+                    .push(", ")
+                    .push_bind(id)
+                    .push(")");
+            }
+            (EventSort::Relevance, Some(EventCursor::Rank(rank, id))) => {
+                q.push(" AND (events_fts.rank, e.id) > (")
+                    .push_bind(rank)
+                    .push(", ")
+                    .push_bind(id)
+                    .push(")");
+            }
+            (_, None) => {}
+            (_, Some(cursor)) => anyhow::bail!("cursor={cursor} doesn't match sort={sort:?}"),
+        }
+
+        q.push(match sort {
+            EventSort::Soonest => " ORDER BY e.start_at ASC, e.id ASC",
+            EventSort::Latest => " ORDER BY e.start_at DESC, e.id DESC",
+            EventSort::Relevance => " ORDER BY events_fts.rank ASC, e.id ASC",
+        });
+        // Fetch one extra row to tell whether there's another page.
+        q.push(" LIMIT ").push_bind(search.limit + 1);
+
+        let mut rows = q.build_query_as::<RankedEvent>().fetch_all(&self.pool).await?;
+
+        let next = match rows.len() > search.limit as usize {
+            true => {
+                rows.truncate(search.limit as usize);
+                rows.last().map(|last| match sort {
+                    EventSort::Relevance => EventCursor::Rank(last.rank, last.event.id),
+                    _ => EventCursor::Date(last.event.start_at, last.event.id),
+                })
+            }
+            false => None,
+        };
+        let events = rows.into_iter().map(|row| row.event).collect();
+        Ok(EventPage { events, next })
+    }
+    // Get all distinct Event venues
+    pub async fn get_event_venues(&self) -> Result<Vec<String>> {
+        let rows = sqlx::query_as::<_, (String,)>(
+            "SELECT DISTINCT venue FROM events WHERE venue != '' ORDER BY venue COLLATE NOCASE",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|r| r.0).collect())
+    }
+    // Get all distinct Event tags
+    pub async fn get_event_tags(&self) -> Result<Vec<String>> {
+        let rows = sqlx::query_as::<_, (String,)>("SELECT DISTINCT tag FROM event_tags ORDER BY tag")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(|r| r.0).collect())
+    }
+    // Get all distinct Event tags, with how many events have each
+    pub async fn get_event_tag_counts(&self) -> Result<Vec<TagCount>> {
+        let tags = sqlx::query_as::<_, TagCount>(
+            "SELECT tag, COUNT(*) AS events FROM event_tags GROUP BY tag ORDER BY tag",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(tags)
+    }
+    /// Rename a tag on every event and template, merging it into `to` if that tag already exists.
+    pub async fn rename_event_tag(&self, from: &str, to: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        // Tags are case-insensitive, so a change of case is a plain rename. Otherwise, events
+        // which already have both tags would end up with a duplicate.
+        if !from.eq_ignore_ascii_case(to) {
+            sqlx::query(
+                "DELETE FROM event_tags WHERE tag = ? \
+                 AND event_id IN (SELECT event_id FROM event_tags WHERE tag = ?)",
+            )
+            .bind(from)
+            .bind(to)
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query("UPDATE event_tags SET tag = ? WHERE tag = ?")
+            .bind(to)
+            .bind(from)
+            .execute(&mut *tx)
+            .await?;
+
+        let templates = sqlx::query_as::<_, (i64, String)>("SELECT id, tags FROM event_templates")
+            .fetch_all(&mut *tx)
+            .await?;
+        for (id, tags) in templates {
+            let mut renamed: Vec<&str> = vec![];
+            for tag in tags.split(',').map(str::trim).filter(|tag| !tag.is_empty()) {
+                let tag = if tag.eq_ignore_ascii_case(from) { to } else { tag };
+                if !renamed.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                    renamed.push(tag);
+                }
+            }
+            let renamed = renamed.join(", ");
+            if renamed != tags {
+                sqlx::query("UPDATE event_templates SET tags = ? WHERE id = ?")
+                    .bind(renamed)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+    // Get the tags of an Event
+    pub async fn lookup_tags_by_event_id(&self, id: i64) -> Result<Vec<String>> {
+        let rows =
+            sqlx::query_as::<_, (String,)>("SELECT tag FROM event_tags WHERE event_id = ? ORDER BY tag")
+                .bind(id)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(rows.into_iter().map(|r| r.0).collect())
+    }
+    // Create Event
+    pub async fn create_event(&self, event: &EventFields, editor_id: Option<i64>) -> Result<i64> {
+        let mut tx = self.pool.begin().await?;
+        let event = &EventFields { slug: event_slug(&mut tx, event, None).await?, ..event.clone() };
+        sqlx::query("DELETE FROM event_old_slugs WHERE slug = ?")
+            .bind(&event.slug)
+            .execute(&mut *tx)
+            .await?;
+        let row = sqlx::query(
+            "INSERT INTO events (slug, title, artist, description, venue, timezone, doors_at, start_at, end_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&event.slug)
+        .bind(&event.title)
+        .bind(&event.artist)
+        .bind(&event.description)
+        .bind(&event.venue)
+        .bind(event.timezone.name())
+        .bind(event.doors_at)
+        .bind(event.start_at)
+        .bind(event.end_at)
+        .execute(&mut *tx)
+        .await?;
+        let id = row.last_insert_rowid();
+
+        for tag in &event.tags {
+            sqlx::query("INSERT OR IGNORE INTO event_tags (event_id, tag) VALUES (?, ?)")
+                .bind(id)
+                .bind(tag)
+                .execute(&mut *tx)
+                .await?;
+        }
+        insert_revision(&mut tx, RevisionKind::Event, id, editor_id, event).await?;
+
+        tx.commit().await?;
+        Ok(id)
+    }
+    // Update Event
+    pub async fn update_event(&self, id: i64, event: &EventFields, editor_id: Option<i64>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let event = &EventFields { slug: event_slug(&mut tx, event, Some(id)).await?, ..event.clone() };
+        // Keep the old slug around for redirects, and make sure the new one doesn't redirect elsewhere.
+        sqlx::query(
+            "INSERT OR REPLACE INTO event_old_slugs (slug, event_id) \
+             SELECT slug, id FROM events WHERE id = ? AND slug != ?",
+        )
+        .bind(id)
+        .bind(&event.slug)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM event_old_slugs WHERE slug = ?")
+            .bind(&event.slug)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "UPDATE events
+            SET slug = ?, title = ?, artist = ?, description = ?, venue = ?, \
+                timezone = ?, doors_at = ?, start_at = ?, end_at = ?, \
+                updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?",
+        )
+        .bind(&event.slug)
+        .bind(&event.title)
+        .bind(&event.artist)
+        .bind(&event.description)
+        .bind(&event.venue)
+        .bind(event.timezone.name())
+        .bind(event.doors_at)
+        .bind(event.start_at)
+        .bind(event.end_at)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM event_tags WHERE event_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        for tag in &event.tags {
+            sqlx::query("INSERT OR IGNORE INTO event_tags (event_id, tag) VALUES (?, ?)")
+                .bind(id)
+                .bind(tag)
+                .execute(&mut *tx)
+                .await?;
+        }
+        insert_revision(&mut tx, RevisionKind::Event, id, editor_id, event).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+    // Update Event status
+    pub async fn update_event_status(
+        &self,
+        id: i64,
+        status: EventStatus,
+        publish_at: Option<DateTime<Utc>>,
+        rescheduled_at: Option<DateTime<Utc>>,
+    ) -> Result<SqliteQueryResult, Error> {
+        sqlx::query("UPDATE events SET status = ?, publish_at = ?, rescheduled_at = ?, updated_at = CURRENT_TIMESTAMP \
+             WHERE id = ?")
+            .bind(status)
+            .bind(publish_at)
+            .bind(rescheduled_at)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+    }
+    // Publish scheduled Events whose publish time has passed
+    pub async fn publish_scheduled_events(&self, now: DateTime<Utc>) -> Result<u64, Error> {
+        let res = sqlx::query(
+            "UPDATE events SET status = 'published', updated_at = CURRENT_TIMESTAMP \
+             WHERE status = 'scheduled' AND publish_at <= ?",
+        )
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected())
+    }
+    // Remove Event
+    pub async fn delete_event(&self, id: i64) -> Result<SqliteQueryResult, Error> {
+        let mut tx = self.pool.begin().await?;
+        let res = sqlx::query("DELETE FROM events WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM revisions WHERE kind = ? AND record_id = ?")
+            .bind(RevisionKind::Event)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM comments WHERE target = ? AND target_id = ?")
+            .bind(CommentTarget::Event)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(res)
+    }
+
+    // Get all Revisions of a record, oldest first
+    pub async fn get_revisions(&self, kind: RevisionKind, record_id: i64) -> Result<Vec<Revision>> {
+        let revisions = sqlx::query_as::<_, Revision>(
+            "SELECT r.*, u.first_name || ' ' || u.last_name AS editor \
+             FROM revisions r LEFT JOIN users u ON u.id = r.editor_id \
+             WHERE r.kind = ? AND r.record_id = ? ORDER BY r.id",
+        )
+        .bind(kind)
+        .bind(record_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(revisions)
+    }
+    // Lookup Revision of a record by id
+    pub async fn lookup_revision(
+        &self,
+        kind: RevisionKind,
+        record_id: i64,
+        id: i64,
+    ) -> Result<Option<Revision>> {
+        let revision = sqlx::query_as::<_, Revision>(
+            "SELECT r.*, u.first_name || ' ' || u.last_name AS editor \
+             FROM revisions r LEFT JOIN users u ON u.id = r.editor_id \
+             WHERE r.kind = ? AND r.record_id = ? AND r.id = ?",
+        )
+        .bind(kind)
+        .bind(record_id)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(revision)
+    }
+
+    // Create an EventTemplate from an existing Event's fields
+    pub async fn create_event_template(&self, name: &str, event: &EventFields) -> Result<i64> {
+        let row = sqlx::query(
+            "INSERT INTO event_templates (name, title, artist, description, venue, tags, timezone) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(name)
+        .bind(&event.title)
+        .bind(&event.artist)
+        .bind(&event.description)
+        .bind(&event.venue)
+        .bind(event.tags.join(", "))
+        .bind(event.timezone.name())
+        .execute(&self.pool)
+        .await?;
+        Ok(row.last_insert_rowid())
+    }
+    // Get all EventTemplates
+    pub async fn get_event_templates(&self) -> Result<Vec<EventTemplate>> {
+        let templates =
+            sqlx::query_as::<_, EventTemplate>("SELECT * FROM event_templates ORDER BY name COLLATE NOCASE")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(templates)
+    }
+    // Lookup EventTemplate by id
+    pub async fn lookup_event_template_by_id(&self, id: i64) -> Result<Option<EventTemplate>> {
+        let template = sqlx::query_as::<_, EventTemplate>("SELECT * FROM event_templates WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(template)
+    }
+    // Remove EventTemplate
+    pub async fn delete_event_template(&self, id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM event_templates WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // Add an RSVP to an Event
+    pub async fn create_rsvp(&self, event_id: i64, user_id: i64) -> Result<()> {
+        sqlx::query("INSERT OR IGNORE INTO rsvps (event_id, user_id) VALUES (?, ?)")
+            .bind(event_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    // Remove an RSVP from an Event
+    pub async fn delete_rsvp(&self, event_id: i64, user_id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM rsvps WHERE event_id = ? AND user_id = ?")
+            .bind(event_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    // Check whether a User has RSVP'd to an Event
+    pub async fn has_rsvp(&self, event_id: i64, user_id: i64) -> Result<bool> {
+        let row = sqlx::query_as::<_, (i64,)>("SELECT 1 FROM rsvps WHERE event_id = ? AND user_id = ?")
+            .bind(event_id)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.is_some())
+    }
+    // Get all Users who have RSVP'd to an Event
+    pub async fn get_rsvp_users(&self, event_id: i64) -> Result<Vec<User>> {
+        let users = sqlx::query_as::<_, User>(
+            "SELECT u.* \
+             FROM rsvps r \
+             JOIN users u ON u.id = r.user_id \
+             WHERE r.event_id = ?",
+        )
+        .bind(event_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(users)
+    }
+
+    // Get everyone going to an Event: those who RSVP'd and those holding paid tickets
+    pub async fn get_event_attendees(&self, event_id: i64) -> Result<Vec<User>> {
+        let users = sqlx::query_as::<_, User>(
+            "SELECT u.* FROM users u \
+             WHERE u.id IN ( \
+                 SELECT user_id FROM rsvps WHERE event_id = ? \
+                 UNION SELECT user_id FROM orders WHERE event_id = ? AND status = 'paid' \
+             )",
+        )
+        .bind(event_id)
+        .bind(event_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(users)
+    }
+
+    // Create a GuestAllocation for an Event
+    pub async fn create_guest_allocation(&self, event_id: i64, name: &str, quota: i64) -> Result<i64> {
+        let token = format!("{:08x}", OsRng.gen::<u64>());
+        let row =
+            sqlx::query("INSERT INTO guest_allocations (event_id, name, quota, token) VALUES (?, ?, ?, ?)")
+                .bind(event_id)
+                .bind(name)
+                .bind(quota)
+                .bind(token)
+                .execute(&self.pool)
+                .await?;
+        Ok(row.last_insert_rowid())
+    }
+    // Remove a GuestAllocation, along with everyone on it
+    pub async fn delete_guest_allocation(&self, event_id: i64, id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM guest_allocations WHERE event_id = ? AND id = ?")
+            .bind(event_id)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    // Get all GuestAllocations for an Event
+    pub async fn get_guest_allocations(&self, event_id: i64) -> Result<Vec<GuestAllocation>> {
+        let allocations = sqlx::query_as::<_, GuestAllocation>(
+            "SELECT a.*, (SELECT COALESCE(SUM(1 + g.plus_ones), 0) FROM guests g WHERE g.allocation_id = a.id) AS used \
+             FROM guest_allocations a \
+             WHERE a.event_id = ? \
+             ORDER BY a.name COLLATE NOCASE",
+        )
+        .bind(event_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(allocations)
+    }
+    // Lookup GuestAllocation by its private token
+    pub async fn lookup_guest_allocation_by_token(&self, token: &str) -> Result<Option<GuestAllocation>> {
+        let allocation = sqlx::query_as::<_, GuestAllocation>(
+            "SELECT a.*, (SELECT COALESCE(SUM(1 + g.plus_ones), 0) FROM guests g WHERE g.allocation_id = a.id) AS used \
+             FROM guest_allocations a \
+             WHERE a.token = ?",
+        )
+        .bind(token)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(allocation)
+    }
+    // Get everyone on a GuestAllocation
+    pub async fn get_guests(&self, allocation_id: i64) -> Result<Vec<Guest>> {
+        let guests = sqlx::query_as::<_, Guest>("SELECT * FROM guests WHERE allocation_id = ? ORDER BY id")
+            .bind(allocation_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(guests)
+    }
+    /// Add someone to a guest list.
+    ///
+    /// Returns `false` without adding them if it would put the list over its quota.
+    pub async fn create_guest(&self, allocation_id: i64, name: &str, plus_ones: i64) -> Result<bool> {
+        // Check the quota in the same statement, so two people filling in the list at once can't overrun it.
+        let res = sqlx::query(
+            "INSERT INTO guests (allocation_id, name, plus_ones) \
+             SELECT a.id, ?, ? FROM guest_allocations a \
+             WHERE a.id = ? \
+               AND a.quota >= 1 + ? + ( \
+                   SELECT COALESCE(SUM(1 + g.plus_ones), 0) FROM guests g WHERE g.allocation_id = a.id \
+               )",
+        )
+        .bind(name)
+        .bind(plus_ones)
+        .bind(allocation_id)
+        .bind(plus_ones)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() == 1)
+    }
+    // Remove someone from a guest list
+    pub async fn delete_guest(&self, allocation_id: i64, id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM guests WHERE allocation_id = ? AND id = ?")
+            .bind(allocation_id)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // Get everyone expected at the door of an Event, from both RSVPs and the guest list
+    pub async fn get_door_entries(&self, event_id: i64) -> Result<Vec<DoorEntry>> {
+        let entries = sqlx::query_as::<_, DoorEntry>(
+            "SELECT 'rsvp' AS kind, u.id AS id, u.first_name || ' ' || u.last_name AS name, \
+                    0 AS plus_ones, NULL AS list, r.checked_in_at AS checked_in_at \
+             FROM rsvps r \
+             JOIN users u ON u.id = r.user_id \
+             WHERE r.event_id = ? \
+             UNION ALL \
+             SELECT 'guest', g.id, g.name, g.plus_ones, a.name, g.checked_in_at \
+             FROM guests g \
+             JOIN guest_allocations a ON a.id = g.allocation_id \
+             WHERE a.event_id = ? \
+             ORDER BY name COLLATE NOCASE",
+        )
+        .bind(event_id)
+        .bind(event_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(entries)
+    }
+    // Check someone in at the door, or undo it if `checked_in` is false
+    pub async fn check_in_rsvp(&self, event_id: i64, user_id: i64, checked_in: bool) -> Result<()> {
+        let checked_in_at = checked_in.then(Utc::now);
+        sqlx::query("UPDATE rsvps SET checked_in_at = ? WHERE event_id = ? AND user_id = ?")
+            .bind(checked_in_at)
+            .bind(event_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    // Check a guest in at the door, or undo it if `checked_in` is false
+    pub async fn check_in_guest(&self, event_id: i64, guest_id: i64, checked_in: bool) -> Result<()> {
+        let checked_in_at = checked_in.then(Utc::now);
+        sqlx::query(
+            "UPDATE guests SET checked_in_at = ? \
+             WHERE id = ? AND allocation_id IN (SELECT id FROM guest_allocations WHERE event_id = ?)",
+        )
+        .bind(checked_in_at)
+        .bind(guest_id)
+        .bind(event_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    // Add a Photo to an Event's gallery
+    pub async fn create_photo(
+        &self,
+        event_id: i64,
+        filename: &str,
+        format: &str,
+        width: u32,
+        height: u32,
+    ) -> Result<i64> {
+        let row = sqlx::query(
+            "INSERT INTO photos (event_id, filename, format, width, height) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(event_id)
+        .bind(filename)
+        .bind(format)
+        .bind(width)
+        .bind(height)
+        .execute(&self.pool)
+        .await?;
+        Ok(row.last_insert_rowid())
+    }
+    // Get the Photos in an Event's gallery, in upload order
+    pub async fn get_photos(&self, event_id: i64, include_hidden: bool) -> Result<Vec<Photo>> {
+        let photos = sqlx::query_as::<_, Photo>(
+            "SELECT * FROM photos WHERE event_id = ? AND (? OR NOT hidden) ORDER BY id",
+        )
+        .bind(event_id)
+        .bind(include_hidden)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(photos)
+    }
+    // Lookup Photo by id
+    pub async fn lookup_photo_by_id(&self, id: i64) -> Result<Option<Photo>> {
+        let photo = sqlx::query_as::<_, Photo>("SELECT * FROM photos WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(photo)
+    }
+    // Hide a Photo from the public, or show it again
+    pub async fn set_photo_hidden(&self, event_id: i64, id: i64, hidden: bool) -> Result<()> {
+        sqlx::query("UPDATE photos SET hidden = ? WHERE event_id = ? AND id = ?")
+            .bind(hidden)
+            .bind(event_id)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    // Set or clear the Photo shown at the top of an Event's page
+    pub async fn set_event_cover(&self, event_id: i64, photo_id: Option<i64>) -> Result<()> {
+        // The subquery keeps photos from other events from being picked.
+        sqlx::query("UPDATE events SET cover_photo_id = (SELECT id FROM photos WHERE event_id = ? AND id = ?) WHERE id = ?")
+            .bind(event_id)
+            .bind(photo_id)
+            .bind(event_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    // Remove a Photo, returning it so its files can be cleaned up
+    pub async fn delete_photo(&self, event_id: i64, id: i64) -> Result<Option<Photo>> {
+        let photo =
+            sqlx::query_as::<_, Photo>("DELETE FROM photos WHERE event_id = ? AND id = ? RETURNING *")
+                .bind(event_id)
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(photo)
+    }
+    // Of the given Events, get the ones which have a public gallery
+    pub async fn get_event_ids_with_photos(&self, event_ids: &[i64]) -> Result<Vec<i64>> {
+        if event_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut q = QueryBuilder::<Sqlite>::new(
+            "SELECT DISTINCT event_id FROM photos WHERE NOT hidden AND event_id IN (",
+        );
+        let mut ids = q.separated(", ");
+        for id in event_ids {
+            ids.push_bind(id);
+        }
+        q.push(")");
+        let rows = q.build_query_as::<(i64,)>().fetch_all(&self.pool).await?;
+        Ok(rows.into_iter().map(|r| r.0).collect())
+    }
+
+    // Add a Stage to the end of an Event's timetable
+    pub async fn create_stage(&self, event_id: i64, name: &str) -> Result<i64> {
+        let row = sqlx::query(
+            "INSERT INTO stages (event_id, name, position) \
+             SELECT ?, ?, COALESCE(MAX(position), 0) + 1 FROM stages WHERE event_id = ?",
+        )
+        .bind(event_id)
+        .bind(name)
+        .bind(event_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(row.last_insert_rowid())
+    }
+    // Rename a Stage, or move it within the timetable
+    pub async fn update_stage(&self, event_id: i64, id: i64, name: &str, position: i64) -> Result<()> {
+        sqlx::query("UPDATE stages SET name = ?, position = ? WHERE event_id = ? AND id = ?")
+            .bind(name)
+            .bind(position)
+            .bind(event_id)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    // Remove a Stage, along with its Slots
+    pub async fn delete_stage(&self, event_id: i64, id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM stages WHERE event_id = ? AND id = ?")
+            .bind(event_id)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    // Get all Stages for an Event, in timetable order
+    pub async fn get_stages(&self, event_id: i64) -> Result<Vec<Stage>> {
+        let stages =
+            sqlx::query_as::<_, Stage>("SELECT * FROM stages WHERE event_id = ? ORDER BY position, id")
+                .bind(event_id)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(stages)
+    }
+
+    // Get all Slots for an Event, in running order
+    pub async fn get_slots(&self, event_id: i64) -> Result<Vec<Slot>> {
+        let slots = sqlx::query_as::<_, Slot>(
+            "SELECT * FROM slots \
+             WHERE stage_id IN (SELECT id FROM stages WHERE event_id = ?) \
+             ORDER BY start_at, id",
+        )
+        .bind(event_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(slots)
+    }
+    /// Add a Slot to a Stage, if the stage belongs to the event and is free at that time.
+    ///
+    /// Returns whether the slot was added.
+    pub async fn create_slot(
+        &self,
+        event_id: i64,
+        stage_id: i64,
+        artist: &str,
+        start_at: DateTime<Utc>,
+        end_at: DateTime<Utc>,
+    ) -> Result<bool> {
+        let res = sqlx::query(
+            "INSERT INTO slots (stage_id, artist, start_at, end_at) \
+             SELECT id, ?, ?, ? FROM stages WHERE event_id = ? AND id = ? \
+             AND NOT EXISTS ( \
+                SELECT 1 FROM slots WHERE stage_id = ? AND start_at < ? AND end_at > ? \
+             )",
+        )
+        .bind(artist)
+        .bind(start_at)
+        .bind(end_at)
+        .bind(event_id)
+        .bind(stage_id)
+        .bind(stage_id)
+        .bind(end_at)
+        .bind(start_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+    /// Change a Slot, if its stage is free at the new time.
+    ///
+    /// Returns whether the slot was changed.
+    pub async fn update_slot(
+        &self,
+        event_id: i64,
+        id: i64,
+        artist: &str,
+        start_at: DateTime<Utc>,
+        end_at: DateTime<Utc>,
+    ) -> Result<bool> {
+        let res = sqlx::query(
+            "UPDATE slots SET artist = ?, start_at = ?, end_at = ? \
+             WHERE id = ? AND stage_id IN (SELECT id FROM stages WHERE event_id = ?) \
+             AND NOT EXISTS ( \
+                SELECT 1 FROM slots other \
+                WHERE other.stage_id = slots.stage_id AND other.id != slots.id \
+                AND other.start_at < ? AND other.end_at > ? \
+             )",
+        )
+        .bind(artist)
+        .bind(start_at)
+        .bind(end_at)
+        .bind(id)
+        .bind(event_id)
+        .bind(end_at)
+        .bind(start_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+    // Remove a Slot
+    pub async fn delete_slot(&self, event_id: i64, id: i64) -> Result<()> {
+        sqlx::query(
+            "DELETE FROM slots WHERE id = ? AND stage_id IN (SELECT id FROM stages WHERE event_id = ?)",
+        )
+        .bind(id)
+        .bind(event_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    // Add a TicketTier to an Event
+    pub async fn create_ticket_tier(
+        &self,
+        event_id: i64,
+        name: &str,
+        price: i64,
+        capacity: Option<i64>,
+    ) -> Result<i64> {
+        let row =
+            sqlx::query("INSERT INTO ticket_tiers (event_id, name, price, capacity) VALUES (?, ?, ?, ?)")
+                .bind(event_id)
+                .bind(name)
+                .bind(price)
+                .bind(capacity)
+                .execute(&self.pool)
+                .await?;
+        Ok(row.last_insert_rowid())
+    }
+    // Get all TicketTiers for an Event, cheapest first
+    pub async fn get_ticket_tiers(&self, event_id: i64) -> Result<Vec<TicketTier>> {
+        let tiers = sqlx::query_as::<_, TicketTier>(
+            "SELECT t.*, \
+                (SELECT COALESCE(SUM(quantity), 0) FROM orders \
+                 WHERE tier_id = t.id AND status IN ('pending', 'paid')) AS sold \
+             FROM ticket_tiers t WHERE t.event_id = ? ORDER BY t.price, t.id",
+        )
+        .bind(event_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(tiers)
+    }
+    /// Remove a TicketTier, unless anyone has ordered tickets in it.
+    ///
+    /// Returns whether the tier was removed.
+    pub async fn delete_ticket_tier(&self, event_id: i64, id: i64) -> Result<bool> {
+        let res = sqlx::query(
+            "DELETE FROM ticket_tiers WHERE event_id = ? AND id = ? \
+             AND NOT EXISTS (SELECT 1 FROM orders WHERE tier_id = ticket_tiers.id)",
+        )
+        .bind(event_id)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Add a PromoCode to an Event, limited to the given tiers of that event.
+    pub async fn create_promo_code(&self, event_id: i64, fields: &PromoCodeFields) -> Result<i64> {
+        let mut tx = self.pool.begin().await?;
+        let id = sqlx::query(
+            "INSERT INTO promo_codes (event_id, code, kind, amount, max_uses, expires_at, promoter) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(event_id)
+        .bind(&fields.code)
+        .bind(fields.kind)
+        .bind(fields.amount)
+        .bind(fields.max_uses)
+        .bind(fields.expires_at)
+        .bind(&fields.promoter)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        for tier_id in &fields.tier_ids {
+            sqlx::query(
+                "INSERT INTO promo_code_tiers (promo_code_id, tier_id) \
+                 SELECT ?, id FROM ticket_tiers WHERE event_id = ? AND id = ?",
+            )
+            .bind(id)
+            .bind(event_id)
+            .bind(tier_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(id)
+    }
+    // Get all PromoCodes for an Event, along with how they've been used
+    pub async fn get_promo_codes(&self, event_id: i64) -> Result<Vec<PromoCode>> {
+        let codes = sqlx::query_as::<_, PromoCode>(&format!(
+            "{PROMO_CODE_SELECT} WHERE p.event_id = ? ORDER BY p.code"
+        ))
+        .bind(event_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(codes)
+    }
+    // Get PromoCode by ID
+    pub async fn lookup_promo_code_by_id(&self, event_id: i64, id: i64) -> Result<Option<PromoCode>> {
+        let code =
+            sqlx::query_as::<_, PromoCode>(&format!("{PROMO_CODE_SELECT} WHERE p.event_id = ? AND p.id = ?"))
+                .bind(event_id)
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(code)
+    }
+    // Get PromoCode by the code buyers enter, ignoring case
+    pub async fn lookup_promo_code_by_code(&self, event_id: i64, code: &str) -> Result<Option<PromoCode>> {
+        let code = sqlx::query_as::<_, PromoCode>(&format!(
+            "{PROMO_CODE_SELECT} WHERE p.event_id = ? AND p.code = ?"
+        ))
+        .bind(event_id)
+        .bind(code.to_uppercase())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(code)
+    }
+    // Check whether an Event already has a PromoCode
+    pub async fn is_promo_code_taken(&self, event_id: i64, code: &str) -> Result<bool> {
+        let taken = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM promo_codes WHERE event_id = ? AND code = ?)",
+        )
+        .bind(event_id)
+        .bind(code.to_uppercase())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(taken)
+    }
+    // Expire a PromoCode now, unless it's already expired
+    pub async fn expire_promo_code(&self, event_id: i64, id: i64, now: DateTime<Utc>) -> Result<()> {
+        sqlx::query(
+            "UPDATE promo_codes SET expires_at = ? \
+             WHERE event_id = ? AND id = ? AND (expires_at IS NULL OR expires_at > ?)",
+        )
+        .bind(now)
+        .bind(event_id)
+        .bind(id)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+    /// Remove a PromoCode, unless it's been used, so sales stay attributed.
+    ///
+    /// Returns whether the code was removed.
+    pub async fn delete_promo_code(&self, event_id: i64, id: i64) -> Result<bool> {
+        let res = sqlx::query(
+            "DELETE FROM promo_codes WHERE event_id = ? AND id = ? \
+             AND NOT EXISTS (SELECT 1 FROM orders WHERE promo_code_id = promo_codes.id)",
+        )
+        .bind(event_id)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Place an Order, if there are enough tickets left in the tier and the promo code hasn't run out.
+    ///
+    /// Both are checked in the same statement as the insert, so two buyers
+    /// can't take the last ticket at once. Orders start out pending, holding
+    /// their tickets until they're paid for. Returns the new order's ID, or
+    /// `None` if it couldn't be placed.
+    pub async fn create_order(&self, order: &NewOrder) -> Result<Option<i64>> {
+        let res = sqlx::query(
+            "INSERT INTO orders (event_id, tier_id, user_id, quantity, subtotal, discount, total, promo_code_id) \
+             SELECT event_id, id, ?, ?, ?, ?, ?, ? FROM ticket_tiers t WHERE event_id = ? AND id = ? \
+             AND (capacity IS NULL OR capacity >= ? + ( \
+                SELECT COALESCE(SUM(quantity), 0) FROM orders \
+                WHERE tier_id = t.id AND status IN ('pending', 'paid') \
+             )) \
+             AND (? IS NULL OR EXISTS ( \
+                SELECT 1 FROM promo_codes p WHERE p.id = ? AND p.event_id = t.event_id \
+                AND (p.max_uses IS NULL OR p.max_uses > ( \
+                    SELECT COUNT(*) FROM orders WHERE promo_code_id = p.id AND status IN ('pending', 'paid') \
+                )) \
+             ))",
+        )
+        .bind(order.user_id)
+        .bind(order.quantity)
+        .bind(order.subtotal)
+        .bind(order.discount)
+        .bind(order.total)
+        .bind(order.promo_code_id)
+        .bind(order.event_id)
+        .bind(order.tier_id)
+        .bind(order.quantity)
+        .bind(order.promo_code_id)
+        .bind(order.promo_code_id)
+        .execute(&self.pool)
+        .await?;
+        Ok((res.rows_affected() > 0).then(|| res.last_insert_rowid()))
+    }
+    // Get Order by ID
+    pub async fn lookup_order_by_id(&self, id: i64) -> Result<Option<Order>> {
+        let order = sqlx::query_as::<_, Order>(&format!("{ORDER_SELECT} WHERE o.id = ?"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(order)
+    }
+    // Get Order by the payment provider's checkout ID
+    pub async fn lookup_order_by_payment_id(&self, payment_id: &str) -> Result<Option<Order>> {
+        let order = sqlx::query_as::<_, Order>(&format!("{ORDER_SELECT} WHERE o.payment_id = ?"))
+            .bind(payment_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(order)
+    }
+    // Get all Orders for an Event, newest first
+    pub async fn get_event_orders(&self, event_id: i64) -> Result<Vec<Order>> {
+        let orders =
+            sqlx::query_as::<_, Order>(&format!("{ORDER_SELECT} WHERE o.event_id = ? ORDER BY o.id DESC"))
+                .bind(event_id)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(orders)
+    }
+    // Check whether anyone has paid for, or is paying for, tickets to an Event
+    pub async fn event_has_orders(&self, event_id: i64) -> Result<bool> {
+        let has_orders = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM orders WHERE event_id = ? AND status IN ('pending', 'paid'))",
+        )
+        .bind(event_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(has_orders)
+    }
+    // Record the payment provider's checkout for an Order
+    pub async fn set_order_payment_id(&self, id: i64, payment_id: &str) -> Result<()> {
+        sqlx::query("UPDATE orders SET payment_id = ? WHERE id = ?")
+            .bind(payment_id)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    /// Mark a pending Order as paid, and issue its tickets.
+    ///
+    /// Payment providers can send the same webhook more than once, so this
+    /// does nothing if the order isn't pending any more. Returns whether the
+    /// order was marked paid.
+    pub async fn mark_order_paid(
+        &self,
+        id: i64,
+        payment_ref: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let quantity = sqlx::query_scalar::<_, i64>(
+            "UPDATE orders SET status = 'paid', payment_ref = ?, paid_at = ? \
+             WHERE id = ? AND status = 'pending' RETURNING quantity",
+        )
+        .bind(payment_ref)
+        .bind(now)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(quantity) = quantity else {
+            return Ok(false);
+        };
+        for _ in 0..quantity {
+            let code = format!("{:016x}", OsRng.gen::<u64>());
+            sqlx::query("INSERT INTO tickets (order_id, code) VALUES (?, ?)")
+                .bind(id)
+                .bind(code)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+    /// Cancel a pending Order, releasing its tickets.
+    ///
+    /// Returns whether the order was cancelled.
+    pub async fn cancel_order(&self, id: i64) -> Result<bool> {
+        let res = sqlx::query("UPDATE orders SET status = 'cancelled' WHERE id = ? AND status = 'pending'")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+    /// Mark a paid Order as refunded, which voids its tickets.
+    ///
+    /// Returns whether the order was refunded.
+    pub async fn refund_order(&self, id: i64, now: DateTime<Utc>) -> Result<bool> {
+        let res = sqlx::query(
+            "UPDATE orders SET status = 'refunded', refunded_at = ? WHERE id = ? AND status = 'paid'",
+        )
+        .bind(now)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+    /// Put an Order back to paid after its refund failed, undoing [`Db::refund_order`].
+    pub async fn unrefund_order(&self, id: i64) -> Result<()> {
+        sqlx::query(
+            "UPDATE orders SET status = 'paid', refunded_at = NULL WHERE id = ? AND status = 'refunded'",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+    // Get all Tickets from an Order
+    pub async fn get_order_tickets(&self, order_id: i64) -> Result<Vec<Ticket>> {
+        let tickets = sqlx::query_as::<_, Ticket>("SELECT * FROM tickets WHERE order_id = ? ORDER BY id")
+            .bind(order_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(tickets)
+    }
+    // Get all Orders which used a PromoCode, newest first
+    pub async fn get_promo_code_orders(&self, promo_code_id: i64) -> Result<Vec<Order>> {
+        let orders = sqlx::query_as::<_, Order>(&format!(
+            "{ORDER_SELECT} WHERE o.promo_code_id = ? ORDER BY o.id DESC"
+        ))
+        .bind(promo_code_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(orders)
+    }
+
+    /// Schedule a job to run at `run_at`.
+    ///
+    /// If a job with the same `key` is still pending, it's rescheduled instead.
+    pub async fn schedule_job(&self, key: &str, payload: &str, run_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO jobs (key, payload, run_at) VALUES (?, ?, ?) \
+             ON CONFLICT (key) DO UPDATE SET payload = excluded.payload, run_at = excluded.run_at \
+             WHERE status = 'pending'",
+        )
+        .bind(key)
+        .bind(payload)
+        .bind(run_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+    /// Claim the next job which is due to run, marking it as running.
+    pub async fn claim_next_job(&self, now: DateTime<Utc>) -> Result<Option<Job>> {
+        let job = sqlx::query_as::<_, Job>(
+            "UPDATE jobs SET status = 'running', attempts = attempts + 1 \
+             WHERE id = ( \
+                SELECT id FROM jobs WHERE status = 'pending' AND run_at <= ? \
+                ORDER BY run_at LIMIT 1 \
+             ) \
+             RETURNING id, key, payload, attempts",
+        )
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(job)
+    }
+    /// Put back any jobs which were left running, e.g. if the server restarted part way through.
+    pub async fn reset_running_jobs(&self) -> Result<u64> {
+        let res = sqlx::query("UPDATE jobs SET status = 'pending' WHERE status = 'running'")
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected())
+    }
+    pub async fn complete_job(&self, id: i64) -> Result<()> {
+        sqlx::query("UPDATE jobs SET status = 'done', last_error = NULL WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    /// Record a job failure, and try again at `retry_at` if there is one.
+    pub async fn fail_job(&self, id: i64, error: &str, retry_at: Option<DateTime<Utc>>) -> Result<()> {
+        let status = match retry_at {
+            Some(_) => "pending",
+            None => "failed",
+        };
+        sqlx::query("UPDATE jobs SET status = ?, last_error = ?, run_at = COALESCE(?, run_at) WHERE id = ?")
+            .bind(status)
+            .bind(error)
+            .bind(retry_at)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    /// Record that the email identified by `key` is being sent.
+    ///
+    /// Returns `false` if it was already sent, in which case it shouldn't be sent again.
+    pub async fn mark_email_sent(&self, key: &str) -> Result<bool> {
+        let res = sqlx::query("INSERT OR IGNORE INTO sent_emails (key) VALUES (?)")
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() == 1)
+    }
+
+    /// Create a post, credited to `author_ids` with the lead author first.
+    pub async fn create_post(
+        &self,
+        post: &PostFields,
+        author_ids: &[i64],
+        editor_id: Option<i64>,
+    ) -> Result<i64> {
+        let mut tx = self.pool.begin().await?;
+        let post = &PostFields { slug: post_slug(&mut tx, post, None).await?, ..post.clone() };
+        sqlx::query("DELETE FROM post_old_slugs WHERE slug = ?")
+            .bind(&post.slug)
+            .execute(&mut *tx)
+            .await?;
+        let preview_token = format!("{:016x}{:016x}", OsRng.gen::<u64>(), OsRng.gen::<u64>());
+        // The byline is only kept for old posts, but older databases have no default for it.
+        let row = sqlx::query(
+            "INSERT INTO posts (title, slug, author, body, preview_token) VALUES (?, ?, '', ?, ?)",
+        )
+        .bind(&post.title)
+        .bind(&post.slug)
+        .bind(&post.body)
+        .bind(preview_token)
+        .execute(&mut *tx)
+        .await?;
+        let id = row.last_insert_rowid();
+        set_post_authors(&mut tx, id, author_ids).await?;
+        insert_revision(&mut tx, RevisionKind::Post, id, editor_id, post).await?;
+
+        tx.commit().await?;
+        Ok(id)
+    }
+    pub async fn lookup_post_by_id(&self, id: i64) -> Result<Option<Post>> {
+        let post = sqlx::query_as::<_, Post>(&format!("{POST_SELECT} WHERE p.id = ?"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(post)
+    }
+
+    /// Check whether a post other than `except_id` already has a slug.
+    pub async fn is_post_slug_taken(&self, slug: &str, except_id: Option<i64>) -> Result<bool> {
+        let taken = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM posts WHERE slug = ? AND id IS NOT ?)",
+        )
+        .bind(slug)
+        .bind(except_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(taken)
+    }
+
+    pub async fn lookup_post_by_slug(&self, slug: &str) -> Result<Option<Post>> {
+        let row = sqlx::query_as::<_, Post>(&format!("{POST_SELECT} WHERE p.slug = ?"))
+            .bind(slug)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row)
+    }
+
+    /// Find published posts, newest first, a page at a time.
+    pub async fn search_posts(&self, search: &PostSearch) -> Result<PostPage> {
+        let mut q = QueryBuilder::<Sqlite>::new(POST_SELECT);
+        q.push(" WHERE p.status = 'published'");
+        if let Some(author_id) = search.author_id {
+            q.push(" AND EXISTS (SELECT 1 FROM post_authors a WHERE a.post_id = p.id AND a.user_id = ")
+                .push_bind(author_id)
+                .push(")");
+        }
+        if let Some(published_after) = search.published_after {
+            q.push(" AND p.published_at >= ").push_bind(published_after);
+        }
+        if let Some(published_before) = search.published_before {
+            q.push(" AND p.published_at < ").push_bind(published_before);
+        }
+        if let Some(PostCursor(published_at, id)) = search.cursor {
+            q.push(" AND (p.published_at, p.id) < (")
+                .push_bind(published_at)
+                .push(", ")
+                .push_bind(id)
+                .push(")");
+        }
+        q.push(" ORDER BY p.published_at DESC, p.id DESC");
+        // Fetch one extra row to tell whether there's another page.
+        q.push(" LIMIT ").push_bind(search.limit + 1);
+
+        let mut posts = q.build_query_as::<Post>().fetch_all(&self.pool).await?;
+        let next = match posts.len() > search.limit as usize {
+            true => {
+                posts.truncate(search.limit as usize);
+                posts.last().and_then(|last| Some(PostCursor(last.published_at?, last.id)))
+            }
+            false => None,
+        };
+        Ok(PostPage { posts, next })
+    }
+
+    /// Count published posts by month, newest first, with months in `tz`.
+    pub async fn get_post_archive(&self, tz: Tz) -> Result<Vec<PostArchiveMonth>> {
+        let published = sqlx::query_as::<_, (DateTime<Utc>,)>(
+            "SELECT published_at FROM posts WHERE status = 'published' ORDER BY published_at DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut months: Vec<PostArchiveMonth> = vec![];
+        for (published_at,) in published {
+            let local = published_at.with_timezone(&tz);
+            let (year, month) = (local.year(), local.month());
+            match months.last_mut() {
+                Some(last) if last.year == year && last.month == month => last.posts += 1,
+                _ => months.push(PostArchiveMonth {
+                    year,
+                    month,
+                    name: local.format("%B %Y").to_string(),
+                    posts: 1,
+                }),
+            }
+        }
+        Ok(months)
+    }
+
+    /// Everyone who has written a published post, for filtering by author.
+    pub async fn get_post_authors(&self) -> Result<Vec<PostAuthor>> {
+        let authors = sqlx::query_as::<_, PostAuthor>(
+            "SELECT DISTINCT u.id AS user_id, u.first_name || ' ' || u.last_name AS name \
+             FROM post_authors a \
+             JOIN posts p ON p.id = a.post_id \
+             JOIN users u ON u.id = a.user_id \
+             WHERE p.status = 'published' \
+             ORDER BY name COLLATE NOCASE",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(authors)
+    }
+
+    /// Look up someone who has written a published post, for their profile page.
+    pub async fn lookup_post_author(&self, user_id: i64) -> Result<Option<PostAuthor>> {
+        let author = sqlx::query_as::<_, PostAuthor>(
+            "SELECT u.id AS user_id, u.first_name || ' ' || u.last_name AS name FROM users u \
+             WHERE u.id = ? AND EXISTS ( \
+                SELECT 1 FROM post_authors a JOIN posts p ON p.id = a.post_id \
+                WHERE a.user_id = u.id AND p.status = 'published' \
+             )",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(author)
+    }
+
+    /// Look up a post by a slug it used to have.
+    pub async fn lookup_post_by_old_slug(&self, slug: &str) -> Result<Option<Post>> {
+        let row = sqlx::query_as::<_, Post>(&format!(
+            "{POST_SELECT} JOIN post_old_slugs o ON o.post_id = p.id WHERE o.slug = ?"
+        ))
+        .bind(slug)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    /// Update a post, and credit it to `author_ids` if they're given.
+    pub async fn update_post(
+        &self,
+        id: i64,
+        post: &PostFields,
+        author_ids: Option<&[i64]>,
+        editor_id: Option<i64>,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let post = &PostFields { slug: post_slug(&mut tx, post, Some(id)).await?, ..post.clone() };
+        // Keep the old slug around for redirects, and make sure the new one doesn't redirect elsewhere.
+        sqlx::query(
+            "INSERT OR REPLACE INTO post_old_slugs (slug, post_id) \
+             SELECT slug, id FROM posts WHERE id = ? AND slug != ?",
+        )
+        .bind(id)
+        .bind(&post.slug)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM post_old_slugs WHERE slug = ?")
+            .bind(&post.slug)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "UPDATE posts SET title = ?, slug = ?, body = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+        )
+        .bind(&post.title)
+        .bind(&post.slug)
+        .bind(&post.body)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        if let Some(author_ids) = author_ids {
+            set_post_authors(&mut tx, id, author_ids).await?;
+        }
+        insert_revision(&mut tx, RevisionKind::Post, id, editor_id, post).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Publish, schedule, or unpublish a post, stamping it with `now` the first time it's published.
+    ///
+    /// `publish_at` and `newsletter_on_publish` only apply to scheduled posts, and are cleared otherwise.
+    pub async fn update_post_status(
+        &self,
+        id: i64,
+        status: PostStatus,
+        publish_at: Option<DateTime<Utc>>,
+        newsletter_on_publish: bool,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE posts SET status = ?, publish_at = ?, newsletter_on_publish = ?, \
+                updated_at = CURRENT_TIMESTAMP, \
+                published_at = CASE WHEN ? = 'published' THEN COALESCE(published_at, ?) ELSE published_at END \
+             WHERE id = ?",
+        )
+        .bind(status)
+        .bind(publish_at)
+        .bind(newsletter_on_publish)
+        .bind(status)
+        .bind(now)
+        .bind(id)
+        .execute(&self.pool)
         .await?;
-        Ok(row)
+        Ok(())
     }
-    pub async fn lookup_user_from_session_token(&self, token: &str) -> Result<Option<User>> {
-        let user = sqlx::query_as::<_, User>(
-            "SELECT u.* \
-             FROM session_tokens t \
-             JOIN users u on u.id = t.user_id \
-             WHERE token = ?",
+
+    /// Publish every scheduled post whose time has come, returning their IDs.
+    ///
+    /// They're stamped with the time they were scheduled for, unless they were published before.
+    pub async fn publish_scheduled_posts(&self, now: DateTime<Utc>) -> Result<Vec<i64>> {
+        let ids = sqlx::query_scalar(
+            "UPDATE posts SET status = 'published', updated_at = CURRENT_TIMESTAMP, \
+                published_at = COALESCE(published_at, publish_at) \
+             WHERE status = 'scheduled' AND publish_at <= ? \
+             RETURNING id",
         )
-        .bind(token)
-        .fetch_optional(&self.pool)
+        .bind(now)
+        .fetch_all(&self.pool)
         .await?;
-        Ok(user)
+        Ok(ids)
     }
 
-    pub async fn create_session_token(&self, user_id: i64) -> Result<String> {
-        let token = format!("{:08x}", OsRng.gen::<u64>());
+    pub async fn delete_post(&self, id: i64) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM posts WHERE id = ?").bind(id).execute(&mut *tx).await?;
+        sqlx::query("DELETE FROM revisions WHERE kind = ? AND record_id = ?")
+            .bind(RevisionKind::Post)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM comments WHERE target = ? AND target_id = ?")
+            .bind(CommentTarget::Post)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
 
-        sqlx::query("INSERT INTO session_tokens (user_id, token) VALUES (?, ?)")
-            .bind(user_id)
-            .bind(&token)
+    /// Cache the rendered HTML of a revision, so it's only rendered once.
+    pub async fn set_revision_html(&self, id: i64, html: &str) -> Result<()> {
+        sqlx::query("UPDATE revisions SET html = ? WHERE id = ?")
+            .bind(html)
+            .bind(id)
             .execute(&self.pool)
             .await?;
+        Ok(())
+    }
 
-        Ok(token)
+    pub async fn create_media(
+        &self,
+        filename: &str,
+        content_type: &str,
+        size: usize,
+        dimensions: Option<(u32, u32)>,
+        alt: &str,
+        uploaded_by: i64,
+    ) -> Result<i64> {
+        let row = sqlx::query(
+            "INSERT INTO media (filename, content_type, size, width, height, alt, uploaded_by) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(filename)
+        .bind(content_type)
+        .bind(size as i64)
+        .bind(dimensions.map(|(width, _)| width))
+        .bind(dimensions.map(|(_, height)| height))
+        .bind(alt)
+        .bind(uploaded_by)
+        .execute(&self.pool)
+        .await?;
+        Ok(row.last_insert_rowid())
     }
-    pub async fn create_login_token(&self, email: &Mailbox) -> Result<String> {
-        let token = format!("{:08x}", OsRng.gen::<u64>());
 
-        sqlx::query("INSERT INTO login_tokens (email, token) VALUES (?, ?)")
-            .bind(email.email.to_string())
-            .bind(&token)
+    pub async fn lookup_media_by_id(&self, id: i64) -> Result<Option<Media>> {
+        let media = sqlx::query_as::<_, Media>(&format!("{MEDIA_SELECT} WHERE m.id = ?"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(media)
+    }
+
+    /// Get the media library, newest first, optionally only files whose name or alt text
+    /// contains `query`.
+    pub async fn search_media(&self, query: Option<&str>) -> Result<Vec<Media>> {
+        let mut q = QueryBuilder::<Sqlite>::new(MEDIA_SELECT);
+        if let Some(query) = query {
+            q.push(" WHERE instr(LOWER(m.filename || ' ' || m.alt), LOWER(")
+                .push_bind(query)
+                .push(")) > 0");
+        }
+        q.push(" ORDER BY m.id DESC");
+        let media = q.build_query_as::<Media>().fetch_all(&self.pool).await?;
+        Ok(media)
+    }
+
+    pub async fn update_media_alt(&self, id: i64, alt: &str) -> Result<()> {
+        sqlx::query("UPDATE media SET alt = ? WHERE id = ?")
+            .bind(alt)
+            .bind(id)
             .execute(&self.pool)
             .await?;
+        Ok(())
+    }
 
-        Ok(token)
+    /// Get the posts, published or not, whose current body links to `url`.
+    pub async fn get_posts_linking_to(&self, url: &str) -> Result<Vec<Post>> {
+        let posts =
+            sqlx::query_as::<_, Post>(&format!("{POST_SELECT} WHERE instr(p.body, ?) > 0 ORDER BY p.id"))
+                .bind(url)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(posts)
     }
-    pub async fn lookup_email_by_login_token(&self, token: &str) -> Result<Option<String>> {
-        let row = sqlx::query_as::<_, (String,)>("SELECT email FROM login_tokens WHERE token = ?")
+
+    /// Opt a user in to the newsletter, if they aren't already.
+    pub async fn subscribe_to_newsletter(&self, user_id: i64) -> Result<()> {
+        let token = format!("{:016x}{:016x}", OsRng.gen::<u64>(), OsRng.gen::<u64>());
+        sqlx::query("INSERT OR IGNORE INTO newsletter_subscribers (user_id, token) VALUES (?, ?)")
+            .bind(user_id)
             .bind(token)
-            .fetch_optional(&self.pool)
+            .execute(&self.pool)
             .await?;
-        Ok(row.map(|r| r.0))
+        Ok(())
     }
-    // Lookup Event by id
-    pub async fn lookup_event_by_event_id(&self, id: &i64) -> Result<Option<Event>> {
-        let event = sqlx::query_as::<_, Event>(
-            "SELECT e.* \
-            FROM events e \
-            WHERE id = ?",
+
+    pub async fn unsubscribe_from_newsletter(&self, user_id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM newsletter_subscribers WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn is_newsletter_subscriber(&self, user_id: i64) -> Result<bool> {
+        let subscribed = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM newsletter_subscribers WHERE user_id = ?)",
         )
-        .bind(id)
-        .fetch_optional(&self.pool)
+        .bind(user_id)
+        .fetch_one(&self.pool)
         .await?;
-        Ok(event)
+        Ok(subscribed)
     }
-    // Get all Events
-    pub async fn get_all_events(&self, date: DateTime<Local>, past: bool) -> Result<Vec<Event>, Error> {
-        let events = if !past {
-            sqlx::query_as::<_, Event>("SELECT e.* FROM events e WHERE start_date >= ?")
-                .bind(date)
-                .fetch_all(&self.pool)
-                .await?
-        } else {
-            sqlx::query_as::<_, Event>("SELECT e.* FROM events e WHERE start_date < ?")
-                .bind(date)
-                .fetch_all(&self.pool)
-                .await?
-        };
-        Ok(events)
+
+    /// Look up who an unsubscribe link from a newsletter belongs to.
+    pub async fn lookup_user_by_unsubscribe_token(&self, token: &str) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            "SELECT u.* FROM newsletter_subscribers s JOIN users u ON u.id = s.user_id WHERE s.token = ?",
+        )
+        .bind(token)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(user)
     }
-    // Create Event
-    pub async fn create_event(
+
+    /// Start emailing a post to everyone who's subscribed right now.
+    pub async fn create_newsletter(
         &self,
-        title: &str,
-        artist: &str,
-        description: &str,
-        start_date: &str,
+        post_id: i64,
+        sent_by: Option<i64>,
+        now: DateTime<Utc>,
     ) -> Result<i64> {
-        let row =
-            sqlx::query("INSERT INTO events (title, artist, description, start_date) VALUES (?, ?, ?, ?)")
-                .bind(title)
-                .bind(artist)
-                .bind(description)
-                .bind(start_date)
-                .execute(&self.pool)
-                .await?;
-        Ok(row.last_insert_rowid())
+        let mut tx = self.pool.begin().await?;
+        let id = sqlx::query("INSERT INTO newsletters (post_id, sent_by, created_at) VALUES (?, ?, ?)")
+            .bind(post_id)
+            .bind(sent_by)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?
+            .last_insert_rowid();
+        sqlx::query(
+            "INSERT INTO newsletter_recipients (newsletter_id, user_id) \
+             SELECT ?, user_id FROM newsletter_subscribers",
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(id)
     }
-    // Update Event
-    pub async fn update_event(
+
+    pub async fn lookup_newsletter_by_id(&self, id: i64) -> Result<Option<Newsletter>> {
+        let newsletter = sqlx::query_as::<_, Newsletter>(&format!("{NEWSLETTER_SELECT} WHERE n.id = ?"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(newsletter)
+    }
+
+    /// Get every time a post was sent as a newsletter, newest first.
+    pub async fn get_post_newsletters(&self, post_id: i64) -> Result<Vec<Newsletter>> {
+        let newsletters = sqlx::query_as::<_, Newsletter>(&format!(
+            "{NEWSLETTER_SELECT} WHERE n.post_id = ? ORDER BY n.id DESC"
+        ))
+        .bind(post_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(newsletters)
+    }
+
+    pub async fn get_newsletter_recipients(&self, newsletter_id: i64) -> Result<Vec<NewsletterRecipient>> {
+        let recipients = sqlx::query_as::<_, NewsletterRecipient>(&format!(
+            "{NEWSLETTER_RECIPIENT_SELECT} WHERE r.newsletter_id = ? ORDER BY u.last_name, u.first_name"
+        ))
+        .bind(newsletter_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(recipients)
+    }
+
+    /// Claim the next `limit` recipients of a newsletter to email, marking them as sending.
+    ///
+    /// Anyone still marked as sending from before was interrupted partway, so they're
+    /// marked as failed rather than risk emailing them twice. Anyone who unsubscribed
+    /// since the newsletter started is skipped.
+    pub async fn claim_newsletter_recipients(
         &self,
-        id: i64,
-        title: &str,
-        artist: &str,
-        description: &str,
-        start_date: &str,
-    ) -> Result<SqliteQueryResult, Error> {
+        newsletter_id: i64,
+        limit: u32,
+    ) -> Result<Vec<NewsletterRecipient>> {
+        let mut tx = self.pool.begin().await?;
         sqlx::query(
-            "UPDATE events
-            SET title = ?, artist = ?, description = ?, start_date = ?
-            WHERE id = ?",
+            "UPDATE newsletter_recipients \
+             SET status = 'failed', error = 'Interrupted while sending, so it may not have gone out.' \
+             WHERE newsletter_id = ? AND status = 'sending'",
         )
-        .bind(title)
-        .bind(artist)
-        .bind(description)
-        .bind(start_date.to_string())
-        .bind(id)
+        .bind(newsletter_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "UPDATE newsletter_recipients SET status = 'skipped' \
+             WHERE newsletter_id = ? AND status = 'pending' \
+             AND user_id NOT IN (SELECT user_id FROM newsletter_subscribers)",
+        )
+        .bind(newsletter_id)
+        .execute(&mut *tx)
+        .await?;
+        let recipients = sqlx::query_as::<_, NewsletterRecipient>(&format!(
+            "{NEWSLETTER_RECIPIENT_SELECT} WHERE r.newsletter_id = ? AND r.status = 'pending' \
+             ORDER BY r.user_id LIMIT ?"
+        ))
+        .bind(newsletter_id)
+        .bind(limit)
+        .fetch_all(&mut *tx)
+        .await?;
+        for recipient in &recipients {
+            sqlx::query(
+                "UPDATE newsletter_recipients SET status = 'sending' WHERE newsletter_id = ? AND user_id = ?",
+            )
+            .bind(newsletter_id)
+            .bind(recipient.user_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(recipients)
+    }
+
+    /// Record how emailing a newsletter to someone went.
+    pub async fn set_newsletter_recipient_status(
+        &self,
+        newsletter_id: i64,
+        user_id: i64,
+        status: RecipientStatus,
+        error: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE newsletter_recipients \
+             SET status = ?, error = ?, sent_at = CASE WHEN ? = 'sent' THEN ? END \
+             WHERE newsletter_id = ? AND user_id = ?",
+        )
+        .bind(status)
+        .bind(error)
+        .bind(status)
+        .bind(now)
+        .bind(newsletter_id)
+        .bind(user_id)
         .execute(&self.pool)
-        .await
+        .await?;
+        Ok(())
     }
-    // Remove Event
-    pub async fn delete_event(&self, id: i64) -> Result<SqliteQueryResult, Error> {
-        sqlx::query("DELETE FROM events WHERE id = ?")
+
+    /// Mark a newsletter as finished, once nobody's left to email.
+    pub async fn finish_newsletter(&self, id: i64, now: DateTime<Utc>) -> Result<()> {
+        sqlx::query("UPDATE newsletters SET finished_at = ? WHERE id = ? AND finished_at IS NULL")
+            .bind(now)
             .bind(id)
             .execute(&self.pool)
-            .await
+            .await?;
+        Ok(())
     }
 
-    pub async fn create_post(&self, title: &str, slug: &str, author: &str, body: &str) -> Result<i64> {
-        let row = sqlx::query("INSERT INTO posts (title, slug, author, body) VALUES (?, ?, ?, ?)")
-            .bind(title)
-            .bind(slug)
-            .bind(author)
+    pub async fn delete_media(&self, id: i64) -> Result<Option<Media>> {
+        let media =
+            sqlx::query_as::<_, Media>("DELETE FROM media WHERE id = ? RETURNING *, NULL AS uploader")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(media)
+    }
+
+    pub async fn create_comment(
+        &self,
+        target: CommentTarget,
+        target_id: i64,
+        parent_id: Option<i64>,
+        user_id: i64,
+        body: &str,
+        status: CommentStatus,
+    ) -> Result<i64> {
+        let id = sqlx::query(
+            "INSERT INTO comments (target, target_id, parent_id, user_id, body, status) \
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(target)
+        .bind(target_id)
+        .bind(parent_id)
+        .bind(user_id)
+        .bind(body)
+        .bind(status)
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(id)
+    }
+    pub async fn lookup_comment_by_id(&self, id: i64) -> Result<Option<Comment>> {
+        let comment = sqlx::query_as::<_, Comment>(&format!("{COMMENT_SELECT} WHERE c.id = ?"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(comment)
+    }
+    /// Get every comment on a post or event, including hidden and deleted ones, oldest first.
+    pub async fn get_comments(&self, target: CommentTarget, target_id: i64) -> Result<Vec<Comment>> {
+        let comments = sqlx::query_as::<_, Comment>(&format!(
+            "{COMMENT_SELECT} WHERE c.target = ? AND c.target_id = ? ORDER BY c.id"
+        ))
+        .bind(target)
+        .bind(target_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(comments)
+    }
+    /// Get the moderation queue, oldest first.
+    pub async fn get_pending_comments(&self) -> Result<Vec<Comment>> {
+        let comments = sqlx::query_as::<_, Comment>(&format!(
+            "{COMMENT_SELECT} WHERE c.status = 'pending' AND c.deleted_at IS NULL ORDER BY c.id"
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(comments)
+    }
+    /// Get the latest comments which have been moderated, newest first.
+    pub async fn get_recent_comments(&self, limit: u32) -> Result<Vec<Comment>> {
+        let comments = sqlx::query_as::<_, Comment>(&format!(
+            "{COMMENT_SELECT} WHERE c.status != 'pending' AND c.deleted_at IS NULL ORDER BY c.id DESC LIMIT ?"
+        ))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(comments)
+    }
+    pub async fn update_comment_body(&self, id: i64, body: &str, now: DateTime<Utc>) -> Result<()> {
+        sqlx::query("UPDATE comments SET body = ?, edited_at = ? WHERE id = ?")
             .bind(body)
+            .bind(now)
+            .bind(id)
             .execute(&self.pool)
             .await?;
-        Ok(row.last_insert_rowid())
+        Ok(())
     }
-    pub async fn lookup_post_by_slug(&self, slug: &str) -> Result<Option<Post>> {
-        let row = sqlx::query_as::<_, Post>("SELECT * FROM posts WHERE slug = ?")
-            .bind(slug)
-            .fetch_optional(&self.pool)
+    pub async fn set_comment_status(&self, id: i64, status: CommentStatus) -> Result<()> {
+        sqlx::query("UPDATE comments SET status = ? WHERE id = ?")
+            .bind(status)
+            .bind(id)
+            .execute(&self.pool)
             .await?;
-        Ok(row)
+        Ok(())
+    }
+    /// Soft-delete a comment, so it's no longer shown but its replies stay in place.
+    pub async fn delete_comment(&self, id: i64, now: DateTime<Utc>) -> Result<()> {
+        sqlx::query("UPDATE comments SET deleted_at = COALESCE(deleted_at, ?) WHERE id = ?")
+            .bind(now)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Stop a user from commenting, and hide everything they've written.
+    pub async fn ban_commenter(&self, user_id: i64, banned_by: i64, now: DateTime<Utc>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT OR IGNORE INTO comment_bans (user_id, banned_by, created_at) VALUES (?, ?, ?)")
+            .bind(user_id)
+            .bind(banned_by)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE comments SET status = 'hidden' WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
     }
+    /// Let a banned user comment again. Their hidden comments stay hidden.
+    pub async fn unban_commenter(&self, user_id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM comment_bans WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    pub async fn is_banned_from_commenting(&self, user_id: i64) -> Result<bool> {
+        let banned = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM comment_bans WHERE user_id = ?)")
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(banned)
+    }
+    pub async fn get_comment_bans(&self) -> Result<Vec<CommentBan>> {
+        let bans = sqlx::query_as::<_, CommentBan>(
+            "SELECT b.user_id, u.first_name || ' ' || u.last_name AS name, u.email, \
+                o.first_name || ' ' || o.last_name AS banned_by, b.created_at \
+             FROM comment_bans b \
+             JOIN users u ON u.id = b.user_id \
+             LEFT JOIN users o ON o.id = b.banned_by \
+             ORDER BY b.created_at DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(bans)
+    }
+
+    /// Get whoever a post or event belongs to, who hear about new comments on it.
+    ///
+    /// That's a post's authors, or whoever created an event.
+    pub async fn get_comment_target_owners(
+        &self,
+        target: CommentTarget,
+        target_id: i64,
+    ) -> Result<Vec<User>> {
+        let query = match target {
+            CommentTarget::Post => {
+                "SELECT u.* FROM post_authors a JOIN users u ON u.id = a.user_id \
+                 WHERE a.post_id = ? ORDER BY a.position"
+            }
+            CommentTarget::Event => {
+                "SELECT u.* FROM users u WHERE u.id = \
+                    (SELECT editor_id FROM revisions WHERE kind = 'event' AND record_id = ? ORDER BY id LIMIT 1)"
+            }
+        };
+        let users = sqlx::query_as::<_, User>(query).bind(target_id).fetch_all(&self.pool).await?;
+        Ok(users)
+    }
+}
+
+/// Whether a query failed because of a `UNIQUE` constraint, like on slugs.
+pub fn is_unique_violation(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<sqlx::Error>()
+        .and_then(|err| err.as_database_error())
+        .is_some_and(|err| err.is_unique_violation())
+}
+
+/// Whether `table` has `column`, for telling which upgrades an older database needs.
+async fn has_column(tx: &mut Transaction<'_, Sqlite>, table: &str, column: &str) -> Result<bool> {
+    let exists = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM pragma_table_info(?) WHERE name = ?)")
+        .bind(table)
+        .bind(column)
+        .fetch_one(&mut **tx)
+        .await?;
+    Ok(exists)
+}
+
+/// Add `column` to a table created by an older version, if it's missing. Returns whether
+/// it was added, so the caller can fill in existing rows.
+async fn add_column(
+    tx: &mut Transaction<'_, Sqlite>,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<bool> {
+    if has_column(tx, table, column).await? {
+        return Ok(false);
+    }
+    sqlx::query(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"))
+        .execute(&mut **tx)
+        .await?;
+    Ok(true)
+}
+
+/// Read an event's old `start_date`: the value of a `datetime-local` input, as wall-clock
+/// time in `timezone`.
+fn legacy_start_date(value: &str, timezone: Tz) -> Result<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date.to_utc());
+    }
+    let naive = [
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%d %H:%M:%S",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    .context("unrecognized date format")?;
+    // A time skipped by daylight saving is moved forward, like the clocks were.
+    timezone
+        .from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| timezone.from_local_datetime(&(naive + TimeDelta::hours(1))).earliest())
+        .map(|date| date.to_utc())
+        .context("time doesn't exist in the timezone")
+}
+
+/// The slug an event gets if none was picked, from its local date and title.
+fn default_event_slug(title: &str, timezone: Tz, start_at: DateTime<Utc>) -> String {
+    let date = start_at.with_timezone(&timezone).format("%Y-%m-%d");
+    let title = slug::slugify(title);
+    let slug = format!("{date}-{title}");
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Pick the slug to save for an event: the one it asked for, or else a unique one
+/// generated from its date and title.
+async fn event_slug(
+    tx: &mut Transaction<'_, Sqlite>,
+    event: &EventFields,
+    id: Option<i64>,
+) -> Result<String> {
+    if !event.slug.is_empty() {
+        return Ok(event.slug.clone());
+    }
+    unique_event_slug(tx, &event.default_slug(), id).await
+}
+
+/// The first of `base`, `base-2`, `base-3`, … not used by an event other than `id`.
+async fn unique_event_slug(tx: &mut Transaction<'_, Sqlite>, base: &str, id: Option<i64>) -> Result<String> {
+    let mut slug = base.to_string();
+    let mut n = 1;
+    loop {
+        let taken = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM events WHERE slug = ? AND id IS NOT ?)",
+        )
+        .bind(&slug)
+        .bind(id)
+        .fetch_one(&mut **tx)
+        .await?;
+        if !taken {
+            return Ok(slug);
+        }
+        n += 1;
+        slug = format!("{base}-{n}");
+    }
+}
+
+/// Credit a post to `author_ids`, in order, replacing anyone credited before.
+///
+/// IDs which aren't users are skipped.
+async fn set_post_authors(tx: &mut Transaction<'_, Sqlite>, post_id: i64, author_ids: &[i64]) -> Result<()> {
+    sqlx::query("DELETE FROM post_authors WHERE post_id = ?")
+        .bind(post_id)
+        .execute(&mut **tx)
+        .await?;
+    for (position, user_id) in author_ids.iter().enumerate() {
+        sqlx::query(
+            "INSERT OR IGNORE INTO post_authors (post_id, user_id, position) \
+             SELECT ?, id, ? FROM users WHERE id = ?",
+        )
+        .bind(post_id)
+        .bind(position as i64)
+        .bind(user_id)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// The slug a post gets if none was picked, from its title.
+fn default_post_slug(title: &str) -> String {
+    match slug::slugify(title) {
+        slug if slug.is_empty() => "post".into(),
+        slug => slug,
+    }
+}
+
+/// Pick the slug to save for a post: the one it asked for, or else a unique one
+/// generated from its title.
+async fn post_slug(tx: &mut Transaction<'_, Sqlite>, post: &PostFields, id: Option<i64>) -> Result<String> {
+    if !post.slug.is_empty() {
+        return Ok(post.slug.clone());
+    }
+    unique_post_slug(tx, &post.default_slug(), id).await
+}
+
+/// The first of `base`, `base-2`, `base-3`, … not used by a post other than `id`.
+async fn unique_post_slug(tx: &mut Transaction<'_, Sqlite>, base: &str, id: Option<i64>) -> Result<String> {
+    let mut slug = base.to_string();
+    let mut n = 1;
+    loop {
+        let taken = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM posts WHERE slug = ? AND id IS NOT ?)",
+        )
+        .bind(&slug)
+        .bind(id)
+        .fetch_one(&mut **tx)
+        .await?;
+        if !taken {
+            return Ok(slug);
+        }
+        n += 1;
+        slug = format!("{base}-{n}");
+    }
+}
+
+/// Save a snapshot of a record as part of the transaction which changed it.
+async fn insert_revision(
+    tx: &mut Transaction<'_, Sqlite>,
+    kind: RevisionKind,
+    record_id: i64,
+    editor_id: Option<i64>,
+    snapshot: &impl serde::Serialize,
+) -> Result<()> {
+    sqlx::query("INSERT INTO revisions (kind, record_id, editor_id, snapshot) VALUES (?, ?, ?, ?)")
+        .bind(kind)
+        .bind(record_id)
+        .bind(editor_id)
+        .bind(serde_json::to_string(snapshot)?)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// Turn free-form user input into an FTS5 query which matches each word as a prefix.
+///
+/// Every word is quoted, so user input can't be interpreted as FTS5 query syntax.
+fn fts_query(input: &str) -> String {
+    let words = input
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")));
+    words.collect::<Vec<_>>().join(" ")
 }
@@ -26,7 +26,13 @@ impl Email {
     }
 
     pub fn builder(&self) -> MessageBuilder {
-        Message::builder().from(self.from.clone()).header(ContentType::TEXT_PLAIN)
+        self.multipart_builder().header(ContentType::TEXT_PLAIN)
+    }
+
+    /// Start a message with several parts, like HTML and plain text versions, which set
+    /// their own content types.
+    pub fn multipart_builder(&self) -> MessageBuilder {
+        Message::builder().from(self.from.clone())
     }
 
     pub async fn send(&self, message: Message) -> Result<()> {
@@ -0,0 +1,120 @@
+//! Minimal Atom (RFC 4287) and RSS 2.0 writers, for news feeds.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+/// A whole feed, which can be rendered as either [`atom`] or [`rss`].
+pub struct Feed<'a> {
+    /// Globally unique and stable ID of the feed.
+    pub id: String,
+    pub title: &'a str,
+    /// Page on the site the feed is for.
+    pub link: String,
+    /// URL of the feed itself.
+    pub self_link: String,
+    /// When any entry was last changed.
+    pub updated: DateTime<Utc>,
+    pub entries: Vec<FeedEntry<'a>>,
+}
+
+/// A single entry in a [`Feed`].
+pub struct FeedEntry<'a> {
+    /// Globally unique and stable ID, so feed readers update the entry instead of duplicating it.
+    pub id: String,
+    pub title: &'a str,
+    pub link: String,
+    /// Empty to leave it out.
+    pub author: String,
+    pub published: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    /// Full content of the entry, as HTML which has already been sanitized.
+    pub content: String,
+}
+
+/// Render a feed as Atom.
+pub fn atom(feed: &Feed) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    element(&mut out, 1, "id", &feed.id);
+    element(&mut out, 1, "title", feed.title);
+    element(&mut out, 1, "updated", &timestamp(feed.updated));
+    // Atom requires an author on the feed if any entry is missing one.
+    out.push_str("  <author><name>WLSD</name></author>\n");
+    out.push_str(&format!("  <link rel=\"alternate\" href=\"{}\"/>\n", escape(&feed.link)));
+    out.push_str(&format!("  <link rel=\"self\" href=\"{}\"/>\n", escape(&feed.self_link)));
+    for entry in &feed.entries {
+        out.push_str("  <entry>\n");
+        element(&mut out, 2, "id", &entry.id);
+        element(&mut out, 2, "title", entry.title);
+        out.push_str(&format!("    <link rel=\"alternate\" href=\"{}\"/>\n", escape(&entry.link)));
+        element(&mut out, 2, "published", &timestamp(entry.published));
+        element(&mut out, 2, "updated", &timestamp(entry.updated));
+        if !entry.author.is_empty() {
+            out.push_str(&format!("    <author><name>{}</name></author>\n", escape(&entry.author)));
+        }
+        out.push_str(&format!("    <content type=\"html\">{}</content>\n", escape(&entry.content)));
+        out.push_str("  </entry>\n");
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+/// Render a feed as RSS 2.0.
+///
+/// RSS has no "updated" time for items, so readers only see edits if they look at the content.
+pub fn rss(feed: &Feed) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str(
+        "<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" \
+         xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n",
+    );
+    out.push_str("<channel>\n");
+    element(&mut out, 1, "title", feed.title);
+    element(&mut out, 1, "link", &feed.link);
+    element(&mut out, 1, "description", feed.title);
+    element(&mut out, 1, "lastBuildDate", &feed.updated.to_rfc2822());
+    out.push_str(&format!(
+        "  <atom:link rel=\"self\" type=\"application/rss+xml\" href=\"{}\"/>\n",
+        escape(&feed.self_link)
+    ));
+    for entry in &feed.entries {
+        out.push_str("  <item>\n");
+        element(&mut out, 2, "title", entry.title);
+        element(&mut out, 2, "link", &entry.link);
+        out.push_str(&format!("    <guid isPermaLink=\"false\">{}</guid>\n", escape(&entry.id)));
+        element(&mut out, 2, "pubDate", &entry.published.to_rfc2822());
+        // RSS's own `author` has to be an email address, so use Dublin Core for names.
+        if !entry.author.is_empty() {
+            element(&mut out, 2, "dc:creator", &entry.author);
+        }
+        element(&mut out, 2, "description", &entry.content);
+        out.push_str("  </item>\n");
+    }
+    out.push_str("</channel>\n</rss>\n");
+    out
+}
+
+/// Format an Atom date, to the second like the database's own timestamps.
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Write `<name>value</name>` on its own line, indented by `depth` levels.
+fn element(out: &mut String, depth: usize, name: &str, value: &str) {
+    out.push_str(&format!("{}<{name}>{}</{name}>\n", "  ".repeat(depth), escape(value)));
+}
+
+/// Escape text for XML, or HTML.
+pub fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,69 @@
+//! A small HTTP client, for calling other services' APIs.
+
+use std::sync::{Arc, LazyLock};
+
+use anyhow::{bail, Context, Result};
+use axum::http::{header, Request, StatusCode, Uri};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper_util::rt::TokioIo;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    rustls::{pki_types::ServerName, ClientConfig, RootCertStore},
+    TlsConnector,
+};
+
+/// TLS settings shared by every request, trusting the usual web root certificates.
+static TLS: LazyLock<TlsConnector> = LazyLock::new(|| {
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+    let roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+    let config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+    TlsConnector::from(Arc::new(config))
+});
+
+/// `POST` a `body` to an `http` or `https` `url`, returning the response's status and body.
+pub async fn post(url: &str, headers: &[(&str, &str)], body: String) -> Result<(StatusCode, Bytes)> {
+    let uri: Uri = url.parse().with_context(|| format!("parsing url={url}"))?;
+    let host = uri.host().context("url has no host")?.to_string();
+    let path = uri.path_and_query().map_or("/", |p| p.as_str());
+    let authority = uri.authority().map_or(host.as_str(), |a| a.as_str());
+
+    let mut req = Request::post(path).header(header::HOST, authority);
+    for (name, value) in headers {
+        req = req.header(*name, *value);
+    }
+    let req = req.body(Full::new(Bytes::from(body)))?;
+
+    match uri.scheme_str() {
+        Some("http") => {
+            let tcp = TcpStream::connect((host.as_str(), uri.port_u16().unwrap_or(80))).await?;
+            send(tcp, req).await
+        }
+        Some("https") => {
+            let tcp = TcpStream::connect((host.as_str(), uri.port_u16().unwrap_or(443))).await?;
+            let tls = TLS.connect(ServerName::try_from(host)?, tcp).await?;
+            send(tls, req).await
+        }
+        _ => bail!("url={url} isn't http or https"),
+    }
+}
+
+/// Send a request over an open connection.
+async fn send<T>(io: T, req: Request<Full<Bytes>>) -> Result<(StatusCode, Bytes)>
+where
+    T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(io)).await?;
+    tokio::spawn(async move {
+        if let Err(err) = conn.await {
+            tracing::warn!("http connection: {err}");
+        }
+    });
+
+    let res = sender.send_request(req).await?;
+    let status = res.status();
+    // Read the whole response, so the connection closes cleanly.
+    let body = res.into_body().collect().await?.to_bytes();
+    Ok((status, body))
+}
@@ -0,0 +1,86 @@
+//! Minimal iCalendar (RFC 5545) writer, for calendar feeds.
+
+use chrono::{DateTime, Utc};
+
+/// A single `VEVENT` in a calendar feed.
+pub struct CalendarEvent<'a> {
+    /// Globally unique and stable ID, so calendar apps update the event instead of duplicating it.
+    pub uid: String,
+    pub url: String,
+    pub summary: &'a str,
+    pub description: &'a str,
+    pub location: &'a str,
+    pub start: DateTime<Utc>,
+    pub end: Option<DateTime<Utc>>,
+    /// When the event was last changed.
+    pub updated: DateTime<Utc>,
+    /// One of `CONFIRMED`, `TENTATIVE`, or `CANCELLED`.
+    pub status: &'static str,
+}
+
+/// Render a whole calendar feed named `name`.
+pub fn calendar(name: &str, events: &[CalendarEvent]) -> String {
+    let mut out = String::new();
+    line(&mut out, "BEGIN", "VCALENDAR");
+    line(&mut out, "VERSION", "2.0");
+    line(&mut out, "PRODID", "-//WLSD//lsd//EN");
+    line(&mut out, "CALSCALE", "GREGORIAN");
+    line(&mut out, "METHOD", "PUBLISH");
+    line(&mut out, "X-WR-CALNAME", &escape(name));
+    for event in events {
+        line(&mut out, "BEGIN", "VEVENT");
+        line(&mut out, "UID", &escape(&event.uid));
+        line(&mut out, "DTSTAMP", &timestamp(event.updated));
+        line(&mut out, "LAST-MODIFIED", &timestamp(event.updated));
+        line(&mut out, "DTSTART", &timestamp(event.start));
+        if let Some(end) = event.end {
+            line(&mut out, "DTEND", &timestamp(end));
+        }
+        line(&mut out, "SUMMARY", &escape(event.summary));
+        if !event.description.is_empty() {
+            line(&mut out, "DESCRIPTION", &escape(event.description));
+        }
+        if !event.location.is_empty() {
+            line(&mut out, "LOCATION", &escape(event.location));
+        }
+        line(&mut out, "URL", &event.url);
+        line(&mut out, "STATUS", event.status);
+        line(&mut out, "END", "VEVENT");
+    }
+    line(&mut out, "END", "VCALENDAR");
+    out
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escape a `TEXT` value.
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Write a content line, folded so no line is longer than 75 bytes.
+fn line(out: &mut String, name: &str, value: &str) {
+    let mut len = 0;
+    for c in name.chars().chain([':']).chain(value.chars()) {
+        if len + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
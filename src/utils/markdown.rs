@@ -0,0 +1,71 @@
+//! Rendering Markdown written by users, like post bodies, into HTML that's safe to show.
+
+use std::{borrow::Cow, sync::LazyLock};
+
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+
+/// Prefix for footnote IDs, so they can't clash with the IDs of the page around them.
+const ID_PREFIX: &str = "fn-";
+
+/// Allowlist of the HTML that Markdown can produce, plus footnote markup.
+///
+/// Raw HTML in the Markdown goes through this too, so anything not on the
+/// list, like scripts, styles, and event handlers, is stripped.
+static SANITIZER: LazyLock<ammonia::Builder<'static>> = LazyLock::new(|| {
+    let mut sanitizer = ammonia::Builder::default();
+    sanitizer
+        .add_tag_attributes("div", &["id"])
+        .add_allowed_classes("div", &["footnote-definition"])
+        .add_allowed_classes("sup", &["footnote-reference", "footnote-definition-label"])
+        .id_prefix(Some(ID_PREFIX))
+        .attribute_filter(|_, attribute, value| match (attribute, value.strip_prefix('#')) {
+            // Keep links to footnotes pointing at their prefixed IDs.
+            ("href", Some(fragment)) => Some(Cow::Owned(format!("#{ID_PREFIX}{fragment}"))),
+            _ => Some(Cow::Borrowed(value)),
+        });
+    sanitizer
+});
+
+/// Render Markdown, with tables, footnotes, and strikethrough, into sanitized HTML.
+pub fn render(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH;
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, Parser::new_ext(markdown, options));
+    SANITIZER.clean(&html).to_string()
+}
+
+/// Pull the plain text from the start of some Markdown, for previews like post listings.
+///
+/// Cut at a word boundary once it's about `max_chars` long, leaving out
+/// headings, code blocks, and footnotes.
+pub fn excerpt(markdown: &str, max_chars: usize) -> String {
+    let mut text = String::new();
+    // How many headings, code blocks, footnotes, etc. we're inside of.
+    let mut skipping = 0;
+    for event in Parser::new_ext(markdown, Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH) {
+        match event {
+            Event::Start(
+                Tag::Heading { .. } | Tag::CodeBlock(_) | Tag::FootnoteDefinition(_) | Tag::Image { .. },
+            ) => skipping += 1,
+            Event::End(
+                TagEnd::Heading(_) | TagEnd::CodeBlock | TagEnd::FootnoteDefinition | TagEnd::Image,
+            ) => skipping -= 1,
+            Event::Text(t) | Event::Code(t) if skipping == 0 => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak | Event::End(TagEnd::Paragraph) if !text.ends_with(' ') => {
+                text.push(' ')
+            }
+            _ => {}
+        }
+        if text.chars().count() > max_chars {
+            break;
+        }
+    }
+
+    let text = text.trim();
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let cut = text.char_indices().nth(max_chars).map_or(text.len(), |(i, _)| i);
+    let cut = text[..cut].rfind(' ').unwrap_or(cut);
+    format!("{}…", text[..cut].trim_end())
+}
@@ -1,6 +1,13 @@
 pub mod config;
 pub mod db;
 pub mod email;
+pub mod feed;
+pub mod http;
+pub mod ics;
+pub mod markdown;
+pub mod payments;
+pub mod slug;
 pub mod tera;
 pub mod tracing;
 pub mod types;
+pub mod webhooks;
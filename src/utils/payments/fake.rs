@@ -0,0 +1,130 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use axum::http::{HeaderMap, HeaderValue};
+use chrono::Utc;
+use rand::{rngs::OsRng, Rng as _};
+
+use super::{parse_event, sign, verify_signature, Checkout, CheckoutSession, PaymentProvider, WebhookEvent};
+
+/// Payment provider which takes no real money, for development.
+///
+/// Checkouts go to a page on this site with buttons to pay or back out,
+/// which send the same signed webhooks Stripe would. Checkouts are only kept
+/// in memory, so they're lost on restart.
+pub struct Fake {
+    /// Base URL of the site, for the checkout pages.
+    url: String,
+    /// Secret to sign webhooks with, made up on startup.
+    secret: String,
+    sessions: Mutex<HashMap<String, FakeSession>>,
+}
+
+/// A checkout waiting to be paid on the fake checkout page.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct FakeSession {
+    pub id: String,
+    pub description: String,
+    pub amount: i64,
+    pub success_url: String,
+    pub cancel_url: String,
+}
+
+impl Fake {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            secret: format!("{:016x}", OsRng.gen::<u64>()),
+            sessions: Mutex::default(),
+        }
+    }
+
+    /// Look up a checkout started with [`PaymentProvider::create_checkout`].
+    pub fn session(&self, id: &str) -> Option<FakeSession> {
+        self.sessions.lock().unwrap().get(id).cloned()
+    }
+
+    /// Build the signed webhook Stripe would send once the buyer pays, or backs out.
+    pub fn webhook(&self, session_id: &str, paid: bool) -> Result<(HeaderMap, Vec<u8>)> {
+        let session = self.session(session_id).context("no such checkout")?;
+        let object = serde_json::json!({
+            "id": session.id,
+            "payment_intent": paid.then(|| session.id.replace("fake_cs_", "fake_pi_")),
+            "payment_status": if paid { "paid" } else { "unpaid" },
+        });
+        let kind = if paid { "checkout.session.completed" } else { "checkout.session.expired" };
+        let body = serde_json::to_vec(&serde_json::json!({ "type": kind, "data": { "object": object } }))?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "Stripe-Signature",
+            HeaderValue::from_str(&sign(&self.secret, &body, Utc::now()))?,
+        );
+        Ok((headers, body))
+    }
+}
+
+#[async_trait]
+impl PaymentProvider for Fake {
+    async fn create_checkout(&self, checkout: &Checkout<'_>) -> Result<CheckoutSession> {
+        let id = format!("fake_cs_{:016x}", OsRng.gen::<u64>());
+        let session = FakeSession {
+            id: id.clone(),
+            description: checkout.description.to_string(),
+            amount: checkout.amount,
+            success_url: checkout.success_url.to_string(),
+            cancel_url: checkout.cancel_url.to_string(),
+        };
+        self.sessions.lock().unwrap().insert(id.clone(), session);
+        let url = format!("{}/payments/fake/{id}", self.url);
+        Ok(CheckoutSession { id, url })
+    }
+
+    fn verify_webhook(&self, headers: &HeaderMap, body: &[u8]) -> Result<WebhookEvent> {
+        let signature = headers.get("Stripe-Signature").context("missing signature")?;
+        verify_signature(&self.secret, signature.to_str()?, body, Utc::now())?;
+        parse_event(body)
+    }
+
+    async fn refund(&self, order_id: i64, payment_ref: &str, amount: i64) -> Result<()> {
+        tracing::info!("fake refund of {amount} cents for order_id={order_id} payment={payment_ref}");
+        Ok(())
+    }
+
+    fn as_fake(&self) -> Option<&Fake> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn webhooks_verify() {
+        let fake = Fake::new("https://localhost");
+        let checkout = Checkout {
+            order_id: 1,
+            description: "1 x GA: Studio Sessions",
+            amount: 1500,
+            success_url: "https://localhost/paid",
+            cancel_url: "https://localhost/cancelled",
+        };
+        let session = fake.create_checkout(&checkout).await.unwrap();
+
+        let (headers, body) = fake.webhook(&session.id, true).unwrap();
+        assert!(matches!(
+            fake.verify_webhook(&headers, &body).unwrap(),
+            WebhookEvent::Paid { session_id, .. } if session_id == session.id
+        ));
+        let (headers, body) = fake.webhook(&session.id, false).unwrap();
+        assert_eq!(
+            fake.verify_webhook(&headers, &body).unwrap(),
+            WebhookEvent::Expired { session_id: session.id.clone() }
+        );
+
+        // Webhooks are signed with a secret made up on startup, so another instance's don't verify.
+        assert!(Fake::new("https://localhost").verify_webhook(&headers, &body).is_err());
+    }
+}
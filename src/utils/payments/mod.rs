@@ -0,0 +1,254 @@
+//! Taking payment for orders, through a card processor.
+//!
+//! Buyers are sent to the provider's hosted checkout page, and the provider
+//! tells us the outcome with a signed webhook. Nothing is trusted until that
+//! webhook's signature checks out.
+
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::utils::config::PaymentsConfig;
+
+mod fake;
+mod stripe;
+
+pub use fake::Fake;
+pub use stripe::Stripe;
+
+/// Something that can take and refund payments.
+#[async_trait]
+pub trait PaymentProvider: Send + Sync {
+    /// Start paying for an order, returning where to send the buyer to pay.
+    async fn create_checkout(&self, checkout: &Checkout<'_>) -> Result<CheckoutSession>;
+
+    /// Check that a webhook really came from the provider, and parse it.
+    fn verify_webhook(&self, headers: &HeaderMap, body: &[u8]) -> Result<WebhookEvent>;
+
+    /// Give back `amount` cents of the payment for `order_id`. Asking again for the
+    /// same order doesn't give it back twice.
+    async fn refund(&self, order_id: i64, payment_ref: &str, amount: i64) -> Result<()>;
+
+    /// The fake provider, if this is it, for confirming payments by hand.
+    fn as_fake(&self) -> Option<&Fake> {
+        None
+    }
+}
+
+/// Set up the configured payment provider.
+pub fn connect(config: Option<PaymentsConfig>, url: &str) -> Option<Arc<dyn PaymentProvider>> {
+    match config {
+        None => None,
+        Some(PaymentsConfig::Fake) => Some(Arc::new(Fake::new(url))),
+        Some(PaymentsConfig::Stripe(config)) => Some(Arc::new(Stripe::new(config))),
+    }
+}
+
+/// What the buyer is paying for.
+pub struct Checkout<'a> {
+    pub order_id: i64,
+    /// Shown to the buyer on the checkout page, e.g. `2 x GA: Studio Sessions`.
+    pub description: &'a str,
+    /// Total to pay, in cents.
+    pub amount: i64,
+    /// Where to send the buyer after paying.
+    pub success_url: &'a str,
+    /// Where to send the buyer if they back out.
+    pub cancel_url: &'a str,
+}
+
+/// A checkout started with the provider.
+pub struct CheckoutSession {
+    /// Provider's ID for the checkout, which webhooks refer back to.
+    pub id: String,
+    /// Where to send the buyer to pay.
+    pub url: String,
+}
+
+/// Something the provider told us about a checkout.
+#[derive(Debug, PartialEq)]
+pub enum WebhookEvent {
+    /// The buyer paid. `payment_ref` identifies the payment, for refunds.
+    Paid { session_id: String, payment_ref: String },
+    /// The buyer never paid, and the checkout can't be used any more.
+    Expired { session_id: String },
+    /// Anything else, which we don't need to act on.
+    Ignored,
+}
+
+/// How old a webhook signature can be, to stop old webhooks from being replayed.
+const SIGNATURE_TOLERANCE_SECS: i64 = 5 * 60;
+
+/// Sign a webhook body, in the format of Stripe's `Stripe-Signature` header: `t=<timestamp>,v1=<hmac>`.
+pub(crate) fn sign(secret: &str, body: &[u8], now: DateTime<Utc>) -> String {
+    let timestamp = now.timestamp();
+    format!("t={timestamp},v1={}", hex::encode(signature(secret, timestamp, body)))
+}
+
+/// Check a signature made by [`sign`].
+pub(crate) fn verify_signature(secret: &str, header: &str, body: &[u8], now: DateTime<Utc>) -> Result<()> {
+    let mut timestamp = None;
+    let mut signatures = vec![];
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", t)) => timestamp = t.parse::<i64>().ok(),
+            Some(("v1", v1)) => signatures.extend(hex::decode(v1).ok()),
+            _ => {}
+        }
+    }
+    let timestamp = timestamp.context("signature is missing a timestamp")?;
+    if (now.timestamp() - timestamp).abs() > SIGNATURE_TOLERANCE_SECS {
+        bail!("signature is too old");
+    }
+
+    let mac = mac(secret, timestamp, body);
+    // There can be more than one signature while the secret is being rolled.
+    for expected in signatures {
+        if mac.clone().verify_slice(&expected).is_ok() {
+            return Ok(());
+        }
+    }
+    bail!("signature doesn't match")
+}
+
+fn signature(secret: &str, timestamp: i64, body: &[u8]) -> Vec<u8> {
+    mac(secret, timestamp, body).finalize().into_bytes().to_vec()
+}
+
+fn mac(secret: &str, timestamp: i64, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+/// Parse a webhook body in Stripe's event format.
+///
+/// The fake provider sends the same format, so it goes through the same code.
+pub(crate) fn parse_event(body: &[u8]) -> Result<WebhookEvent> {
+    #[derive(serde::Deserialize)]
+    struct Event {
+        #[serde(rename = "type")]
+        kind: String,
+        data: Data,
+    }
+    #[derive(serde::Deserialize)]
+    struct Data {
+        object: Session,
+    }
+    #[derive(serde::Deserialize)]
+    struct Session {
+        id: String,
+        payment_intent: Option<String>,
+        payment_status: Option<String>,
+    }
+
+    let event: Event = serde_json::from_slice(body).context("parsing webhook")?;
+    let session = event.data.object;
+    Ok(match event.kind.as_str() {
+        // Checkouts can complete before slower payment methods have gone through.
+        "checkout.session.completed" | "checkout.session.async_payment_succeeded"
+            if session.payment_status.as_deref() == Some("paid") =>
+        {
+            WebhookEvent::Paid {
+                payment_ref: session.payment_intent.context("paid checkout is missing a payment")?,
+                session_id: session.id,
+            }
+        }
+        "checkout.session.expired" | "checkout.session.async_payment_failed" => {
+            WebhookEvent::Expired { session_id: session.id }
+        }
+        _ => WebhookEvent::Ignored,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone as _};
+
+    use super::*;
+
+    const SECRET: &str = "whsec_test";
+    const BODY: &[u8] = br#"{"type":"checkout.session.completed"}"#;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2027, 1, 1, 20, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn accepts_signature() {
+        let header = sign(SECRET, BODY, now());
+        verify_signature(SECRET, &header, BODY, now()).unwrap();
+        verify_signature(SECRET, &header, BODY, now() + Duration::minutes(4)).unwrap();
+    }
+
+    #[test]
+    fn accepts_any_of_several_signatures() {
+        let old = sign("whsec_old", BODY, now());
+        let new = sign(SECRET, BODY, now());
+        let v1 = new.split_once(",v1=").unwrap().1;
+        verify_signature(SECRET, &format!("{old},v1={v1}"), BODY, now()).unwrap();
+    }
+
+    #[test]
+    fn rejects_wrong_secret() {
+        let header = sign("whsec_other", BODY, now());
+        assert!(verify_signature(SECRET, &header, BODY, now()).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_body() {
+        let header = sign(SECRET, BODY, now());
+        let body = br#"{"type":"checkout.session.expired"}"#;
+        assert!(verify_signature(SECRET, &header, body, now()).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_timestamp() {
+        let header = sign(SECRET, BODY, now());
+        let header = header.replace(&now().timestamp().to_string(), &(now().timestamp() + 1).to_string());
+        assert!(verify_signature(SECRET, &header, BODY, now()).is_err());
+    }
+
+    #[test]
+    fn rejects_stale_timestamp() {
+        let header = sign(SECRET, BODY, now());
+        assert!(verify_signature(SECRET, &header, BODY, now() + Duration::minutes(6)).is_err());
+        assert!(verify_signature(SECRET, &header, BODY, now() - Duration::minutes(6)).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_header() {
+        assert!(verify_signature(SECRET, "", BODY, now()).is_err());
+        assert!(verify_signature(SECRET, "v1=00", BODY, now()).is_err());
+        let t = now().timestamp();
+        assert!(verify_signature(SECRET, &format!("t={t}"), BODY, now()).is_err());
+        assert!(verify_signature(SECRET, &format!("t={t},v1=zz"), BODY, now()).is_err());
+    }
+
+    #[test]
+    fn parses_events() {
+        let paid = br#"{"type":"checkout.session.completed","data":{"object":
+            {"id":"cs_1","payment_intent":"pi_1","payment_status":"paid"}}}"#;
+        assert_eq!(
+            parse_event(paid).unwrap(),
+            WebhookEvent::Paid { session_id: "cs_1".into(), payment_ref: "pi_1".into() }
+        );
+
+        let unpaid = br#"{"type":"checkout.session.completed","data":{"object":
+            {"id":"cs_1","payment_intent":null,"payment_status":"unpaid"}}}"#;
+        assert_eq!(parse_event(unpaid).unwrap(), WebhookEvent::Ignored);
+
+        let expired = br#"{"type":"checkout.session.expired","data":{"object":{"id":"cs_1"}}}"#;
+        assert_eq!(
+            parse_event(expired).unwrap(),
+            WebhookEvent::Expired { session_id: "cs_1".into() }
+        );
+    }
+}
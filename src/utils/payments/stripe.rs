@@ -0,0 +1,137 @@
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use axum::http::{header, HeaderMap};
+use chrono::{Duration, Utc};
+
+use super::{parse_event, verify_signature, Checkout, CheckoutSession, PaymentProvider, WebhookEvent};
+use crate::utils::config::StripeConfig;
+use crate::utils::http;
+
+const API_URL: &str = "https://api.stripe.com";
+
+/// Prices are all in US dollars, matching the `format_money` template filter.
+const CURRENCY: &str = "usd";
+
+/// How long a buyer has to pay before their tickets are released.
+///
+/// Stripe won't expire checkouts any sooner than this.
+const CHECKOUT_EXPIRY_MINS: i64 = 30;
+
+/// Card payments through [Stripe Checkout](https://docs.stripe.com/payments/checkout).
+pub struct Stripe {
+    config: StripeConfig,
+}
+
+impl Stripe {
+    pub fn new(config: StripeConfig) -> Self {
+        Self { config }
+    }
+
+    /// Make a form-encoded `POST` to the Stripe API, returning the JSON response.
+    ///
+    /// Requests with the same `idempotency_key` are only carried out once.
+    async fn post(
+        &self,
+        path: &str,
+        params: &[(&str, String)],
+        idempotency_key: Option<&str>,
+    ) -> Result<serde_json::Value> {
+        let authorization = format!("Bearer {}", self.config.secret_key);
+        let mut headers = vec![
+            (header::AUTHORIZATION.as_str(), authorization.as_str()),
+            (header::CONTENT_TYPE.as_str(), "application/x-www-form-urlencoded"),
+        ];
+        if let Some(key) = idempotency_key {
+            headers.push(("Idempotency-Key", key));
+        }
+        let body = serde_urlencoded::to_string(params)?;
+        let (status, body) = http::post(&format!("{API_URL}{path}"), &headers, body).await?;
+        let json: serde_json::Value = serde_json::from_slice(&body).context("parsing stripe response")?;
+        if !status.is_success() {
+            let message = json["error"]["message"].as_str().unwrap_or("unknown error");
+            bail!("stripe {path} returned {status}: {message}");
+        }
+        Ok(json)
+    }
+}
+
+#[async_trait]
+impl PaymentProvider for Stripe {
+    async fn create_checkout(&self, checkout: &Checkout<'_>) -> Result<CheckoutSession> {
+        let expires_at = Utc::now() + Duration::minutes(CHECKOUT_EXPIRY_MINS);
+        // The total already has any promo code taken off, so it's sent as a single line.
+        let params = [
+            ("mode", "payment".to_string()),
+            ("client_reference_id", checkout.order_id.to_string()),
+            ("line_items[0][quantity]", "1".to_string()),
+            ("line_items[0][price_data][currency]", CURRENCY.to_string()),
+            ("line_items[0][price_data][unit_amount]", checkout.amount.to_string()),
+            (
+                "line_items[0][price_data][product_data][name]",
+                checkout.description.to_string(),
+            ),
+            ("success_url", checkout.success_url.to_string()),
+            ("cancel_url", checkout.cancel_url.to_string()),
+            ("expires_at", expires_at.timestamp().to_string()),
+        ];
+        let session = self.post("/v1/checkout/sessions", &params, None).await?;
+        let field = |name: &str| {
+            session[name]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| anyhow!("checkout session is missing `{name}`"))
+        };
+        Ok(CheckoutSession { id: field("id")?, url: field("url")? })
+    }
+
+    fn verify_webhook(&self, headers: &HeaderMap, body: &[u8]) -> Result<WebhookEvent> {
+        let signature = headers.get("Stripe-Signature").context("missing signature")?;
+        verify_signature(&self.config.webhook_secret, signature.to_str()?, body, Utc::now())?;
+        parse_event(body)
+    }
+
+    async fn refund(&self, order_id: i64, payment_ref: &str, amount: i64) -> Result<()> {
+        let params = [
+            ("payment_intent", payment_ref.to_string()),
+            ("amount", amount.to_string()),
+        ];
+        // Stripe replays the first response to a retry with the same key, instead of refunding again.
+        let key = format!("refund-order-{order_id}");
+        self.post("/v1/refunds", &params, Some(&key)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+    use crate::utils::payments::sign;
+
+    fn stripe() -> Stripe {
+        Stripe::new(StripeConfig { secret_key: "sk_test".into(), webhook_secret: "whsec_test".into() })
+    }
+
+    #[test]
+    fn verifies_webhook_signature() {
+        let body = br#"{"type":"checkout.session.expired","data":{"object":{"id":"cs_1"}}}"#;
+        let mut headers = HeaderMap::new();
+        assert!(stripe().verify_webhook(&headers, body).is_err());
+
+        let signature = sign("whsec_test", body, Utc::now());
+        headers.insert("Stripe-Signature", HeaderValue::from_str(&signature).unwrap());
+        assert_eq!(
+            stripe().verify_webhook(&headers, body).unwrap(),
+            WebhookEvent::Expired { session_id: "cs_1".into() }
+        );
+
+        let signature = sign("whsec_other", body, Utc::now());
+        headers.insert("Stripe-Signature", HeaderValue::from_str(&signature).unwrap());
+        assert!(stripe().verify_webhook(&headers, body).is_err());
+
+        let signature = sign("whsec_test", body, Utc::now() - Duration::hours(1));
+        headers.insert("Stripe-Signature", HeaderValue::from_str(&signature).unwrap());
+        assert!(stripe().verify_webhook(&headers, body).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+//! Human-readable URL segments, like `2027-01-01-studio-sessions`.
+
+/// Longest slug allowed, in bytes.
+pub const MAX_LEN: usize = 80;
+
+/// Turn arbitrary text into a slug: lowercase ASCII letters and digits, separated by hyphens.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(MAX_LEN);
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Check that a slug entered by hand is usable in a URL, returning a user-facing message if not.
+///
+/// `reserved` are path segments already used by other routes, like `new`.
+pub fn validate(slug: &str, reserved: &[&str]) -> Result<(), String> {
+    if slug.is_empty() {
+        return Err("The URL can't be empty.".into());
+    }
+    if slug.len() > MAX_LEN {
+        return Err(format!("The URL can't be longer than {MAX_LEN} characters."));
+    }
+    if !slug.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-') {
+        return Err("The URL can only contain lowercase letters, numbers, and hyphens.".into());
+    }
+    if slug.starts_with('-') || slug.ends_with('-') {
+        return Err("The URL can't start or end with a hyphen.".into());
+    }
+    // All-digit URLs are the old numeric IDs.
+    if slug.bytes().all(|b| b.is_ascii_digit()) {
+        return Err("The URL can't be just a number.".into());
+    }
+    if reserved.contains(&slug) {
+        return Err(format!("`{slug}` is reserved, please pick another URL."));
+    }
+    Ok(())
+}
@@ -1,5 +1,6 @@
-use anyhow::{Context, Result};
-use chrono::{DateTime, Local};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, FixedOffset, Local};
+use chrono_tz::Tz;
 use std::collections::HashMap;
 use tera::{Tera, Value};
 
@@ -7,12 +8,16 @@ use tera::{Tera, Value};
 pub fn templates() -> Result<Tera> {
     let mut tera = Tera::new("templates/*")?;
     register_filter(&mut tera, "format_datetime", format_datetime);
+    register_filter(&mut tera, "format_money", format_money);
     Ok(tera)
 }
 
 /// Format a datetime with a [`strftime`] format string.
 ///
-/// Usage: `{{ date | format_datetime(format="%m.%d.%Y") }}`
+/// The date is shown in the server's local timezone, unless an IANA timezone
+/// is passed with the optional `tz` argument.
+///
+/// Usage: `{{ date | format_datetime(format="%m.%d.%Y", tz="America/New_York") }}`
 ///
 /// [`strftime`]: https://devhints.io/strftime
 fn format_datetime(date: &Value, args: &HashMap<String, Value>) -> Result<Value> {
@@ -20,12 +25,31 @@ fn format_datetime(date: &Value, args: &HashMap<String, Value>) -> Result<Value>
     let format = format.as_str().context("arg=`format` must be a string")?;
 
     let date: &str = date.as_str().with_context(|| format!("value={date:?} must be a string"))?;
-    let date: DateTime<Local> = date.parse().context("parsing date")?;
+    let date: DateTime<FixedOffset> = date.parse().context("parsing date")?;
 
-    let formatted = date.format(format).to_string();
+    let formatted = match args.get("tz") {
+        Some(tz) => {
+            let tz = tz.as_str().context("arg=`tz` must be a string")?;
+            let tz: Tz = tz
+                .parse()
+                .map_err(|_| anyhow!("arg=`tz` must be an IANA timezone, got {tz:?}"))?;
+            date.with_timezone(&tz).format(format).to_string()
+        }
+        None => date.with_timezone(&Local).format(format).to_string(),
+    };
     Ok(Value::String(formatted))
 }
 
+/// Format an amount of cents as dollars.
+///
+/// Usage: `{{ tier.price | format_money }}`, e.g. `1250` becomes `$12.50`.
+fn format_money(cents: &Value, _: &HashMap<String, Value>) -> Result<Value> {
+    let cents = cents.as_i64().with_context(|| format!("value={cents:?} must be an integer"))?;
+    let sign = if cents < 0 { "-" } else { "" };
+    let cents = cents.unsigned_abs();
+    Ok(Value::String(format!("{sign}${}.{:02}", cents / 100, cents % 100)))
+}
+
 /// Register a tera filter function.
 ///
 /// On top of the regular `register_filter`, this function adds the filter name
@@ -0,0 +1,27 @@
+//! Webhooks sent to other services, to tell them when something happens on the site.
+//!
+//! Each webhook is a JSON `POST`, signed the same way as Stripe's webhooks. The
+//! `Webhook-Signature` header holds `t=<timestamp>,v1=<hmac>`, where the hmac is
+//! HMAC-SHA256 of `<timestamp>.<body>` using the endpoint's secret.
+
+use anyhow::{bail, Result};
+use axum::http::header;
+use chrono::Utc;
+
+use crate::utils::config::WebhookConfig;
+use crate::utils::http;
+use crate::utils::payments::sign;
+
+/// `POST` a signed JSON `body` to a webhook endpoint, failing unless it responds with success.
+pub async fn send(webhook: &WebhookConfig, body: &str) -> Result<()> {
+    let signature = sign(&webhook.secret, body.as_bytes(), Utc::now());
+    let headers = [
+        (header::CONTENT_TYPE.as_str(), "application/json"),
+        ("Webhook-Signature", signature.as_str()),
+    ];
+    let (status, _) = http::post(&webhook.url, &headers, body.to_string()).await?;
+    if !status.is_success() {
+        bail!("webhook returned {status}");
+    }
+    Ok(())
+}